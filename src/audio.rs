@@ -0,0 +1,155 @@
+use std::f32::consts::TAU;
+
+use crate::consts::AUDIO_RAMP_SECONDS;
+
+/// Shape of the tone played while the sound timer is running.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+pub const WAVEFORMS: [&str; 4] = ["square", "triangle", "sawtooth", "sine"];
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "square" => Some(Waveform::Square),
+            "triangle" => Some(Waveform::Triangle),
+            "sawtooth" | "saw" => Some(Waveform::Sawtooth),
+            "sine" => Some(Waveform::Sine),
+            _ => None,
+        }
+    }
+    /// Value of the wave at `phase` in [0, 1), between -1 and 1.
+    pub fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (TAU * phase).sin(),
+        }
+    }
+}
+
+/// How the buzzer sounds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioSettings {
+    pub waveform: Waveform,
+    /// Tone frequency in Hz.
+    pub frequency: f32,
+    /// Output volume between 0 and 1.
+    pub volume: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            waveform: Waveform::Square,
+            frequency: 440.0,
+            volume: 0.25,
+        }
+    }
+}
+
+/// What the buzzer plays while the sound timer is running.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sound {
+    /// The tone of the [`AudioSettings`].
+    Tone,
+    /// XO-CHIP 1-bit pattern of 128 samples, most significant bit first, looped at `rate` samples per second.
+    Pattern { pattern: [u8; 16], rate: f32 },
+}
+
+/// Tone generator for the buzzer.
+///
+/// The tone fades in and out over a few milliseconds when `playing` changes,
+/// so starting and stopping it on frame boundaries does not click.
+#[derive(Clone, Debug)]
+pub struct Beeper {
+    pub settings: AudioSettings,
+    pub sample_rate: u32,
+    /// Whether the tone should be heard, set once per frame from the sound timer.
+    pub playing: bool,
+    /// XO-CHIP pattern and its rate, played instead of the tone.
+    pub pattern: Option<([u8; 16], f32)>,
+    pub muted: bool,
+    phase: f32,
+    gain: f32,
+}
+
+impl Beeper {
+    pub fn new(settings: AudioSettings, sample_rate: u32) -> Self {
+        Beeper {
+            settings,
+            sample_rate,
+            playing: false,
+            pattern: None,
+            muted: false,
+            phase: 0.0,
+            gain: 0.0,
+        }
+    }
+    /// Plays `sound`, or fades out for `None`.
+    pub fn play(&mut self, sound: Option<Sound>) {
+        self.playing = sound.is_some();
+        match sound {
+            Some(Sound::Pattern { pattern, rate }) => self.pattern = Some((pattern, rate)),
+            Some(Sound::Tone) => self.pattern = None,
+            // keep the pattern while fading out
+            None => {}
+        }
+    }
+    /// Produces the next sample.
+    pub fn sample(&mut self) -> f32 {
+        let target = if self.playing && !self.muted {
+            1.0
+        } else {
+            0.0
+        };
+        let ramp = 1.0 / (AUDIO_RAMP_SECONDS * self.sample_rate as f32);
+        if self.gain < target {
+            self.gain = (self.gain + ramp).min(target);
+        } else {
+            self.gain = (self.gain - ramp).max(target);
+        }
+        if self.gain == 0.0 {
+            // restart the wave so the next tone fades in from the same point
+            self.phase = 0.0;
+            return 0.0;
+        }
+        let value = match self.pattern {
+            Some((pattern, rate)) => {
+                // the phase runs over the 128 bits of the pattern
+                let bit = (self.phase * 128.0) as usize % 128;
+                self.phase = (self.phase + rate / 128.0 / self.sample_rate as f32).fract();
+                if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            None => {
+                let value = self.settings.waveform.sample(self.phase);
+                self.phase =
+                    (self.phase + self.settings.frequency / self.sample_rate as f32).fract();
+                value
+            }
+        };
+        value * self.gain * self.settings.volume
+    }
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.sample();
+        }
+    }
+}
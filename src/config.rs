@@ -0,0 +1,323 @@
+//! Settings for a run, assembled from the defaults of the platform, the config file and the command line.
+//!
+//! The config file is TOML, every setting is optional:
+//!
+//! ```toml
+//! [display]
+//! theme = "amber"          # classic, inverted, amber, green, gameboy or octo
+//! foreground = "#FFB000"   # replaces the colours of the theme
+//! background = "#1A1000"
+//! scale = 7.5              # size of a low resolution pixel in the window
+//! scaling = "fit"          # integer or fit
+//! fullscreen = false
+//! resizable = true
+//! grid = false
+//!
+//! [keys]
+//! preset = "qwerty"        # qwerty, qwertz, azerty or numpad
+//! 5 = ["S", "Space"]       # host keys for a CHIP-8 key, replacing those of the preset
+//!
+//! [controller]
+//! deadzone = 8000          # deflection of the left stick, out of 32767, that still counts as centered
+//! 5 = ["a", "rightshoulder"]
+//! ```
+//!
+//! Settings for a single ROM go into `<rom>.toml` next to it. Settings are layered from the defaults over the global
+//! file, the ROM database (see [`crate::database`]) and `<rom>.toml` to the command line, each taking precedence
+//! over the ones before.
+
+use std::{env, fs, path::Path, path::PathBuf};
+
+use crate::{
+    audio::AudioSettings,
+    consts::{PALETTE, REWIND_SECONDS, SCREENSHOT_SCALE, STICK_DEADZONE, VIDEO_SCALE},
+    database::Profile,
+    display::{self, DisplaySettings, Scaling},
+    error::ConfigError,
+    instruction::Syntax,
+    keymap::{hotkey, Keymap, PRESETS},
+    movie::Movie,
+    platform::Platform,
+    quirks::Quirks,
+    trace::TraceSettings,
+};
+
+/// Settings for a run of the emulator, debugger or disassembler.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    /// Instructions executed per 60 Hz frame.
+    pub instructions_per_frame: u32,
+    /// Seed for CXNN, a random seed is drawn from the OS when unset.
+    pub seed: Option<u64>,
+    pub audio: AudioSettings,
+    /// Path of the ROM, save state slots are stored next to it.
+    pub rom_path: Option<PathBuf>,
+    /// Seconds of history kept for rewinding, 0 disables it.
+    pub rewind_seconds: u32,
+    /// File the keypad input is recorded to as a movie.
+    pub record: Option<PathBuf>,
+    /// Movie whose keypad input replaces the user's until it ends.
+    pub play: Option<Movie>,
+    /// Log every executed instruction.
+    pub trace: Option<TraceSettings>,
+    /// RGB colours for the four bitplane combinations.
+    pub palette: [[u8; 3]; 4],
+    /// Size of a pixel in screenshots, 1 for the native resolution.
+    pub screenshot_scale: u32,
+    /// File or directory every presented frame is recorded to.
+    pub record_video: Option<PathBuf>,
+    /// Size of a low resolution pixel in recorded videos.
+    pub video_scale: u32,
+    pub display: DisplaySettings,
+    pub keymap: Keymap,
+    /// Game controller buttons and the CHIP-8 key they press.
+    pub controller: Keymap,
+    /// Deflection of the left stick, out of 32767, before it counts as a d-pad press.
+    pub stick_deadzone: u16,
+    /// Name of the program from the ROM database, shown in the window title.
+    pub title: Option<String>,
+    /// How the disassembler, the debugger and traces write instructions.
+    pub syntax: Syntax,
+}
+
+impl Config {
+    /// Default quirks and speed of the platform.
+    pub fn new(platform: Platform) -> Self {
+        Config {
+            platform,
+            quirks: platform.default_quirks(),
+            instructions_per_frame: platform.default_instructions_per_frame(),
+            seed: None,
+            audio: AudioSettings::default(),
+            rom_path: None,
+            rewind_seconds: REWIND_SECONDS,
+            record: None,
+            play: None,
+            trace: None,
+            palette: PALETTE,
+            screenshot_scale: SCREENSHOT_SCALE,
+            record_video: None,
+            video_scale: VIDEO_SCALE,
+            display: DisplaySettings::default(),
+            keymap: Keymap::new(),
+            controller: Keymap::controller(),
+            stick_deadzone: STICK_DEADZONE,
+            title: None,
+            syntax: Syntax::default(),
+        }
+    }
+    /// Switches to `platform` with its default quirks and speed.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.quirks = platform.default_quirks();
+        self.instructions_per_frame = platform.default_instructions_per_frame();
+    }
+    /// Sets the speed in instructions per second, rounded to whole instructions per frame.
+    pub fn set_instructions_per_second(&mut self, ips: u32) {
+        self.instructions_per_frame = ((ips + 30) / 60).max(1);
+    }
+    /// Applies the global config file, the database profile of the ROM and `<rom>.toml`, in order of precedence.
+    pub fn load_layers(
+        &mut self,
+        global: Option<&Path>,
+        profile: Option<&Profile>,
+        rom: Option<&Path>,
+    ) -> Result<(), ConfigError> {
+        let load = |config: &mut Config, path: &Path| {
+            config
+                .load_file(path)
+                .map_err(|err| ConfigError::File(path.to_path_buf(), Box::new(err)))
+        };
+        if let Some(path) = global {
+            load(self, path)?;
+        }
+        if let Some(profile) = profile {
+            profile.apply(self);
+        }
+        if let Some(path) = rom {
+            load(self, path)?;
+        }
+        Ok(())
+    }
+    /// Applies the settings of a config file.
+    pub fn load_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        self.load_str(&fs::read_to_string(path)?)
+    }
+    /// Applies the settings of a config file that was already read.
+    pub fn load_str(&mut self, text: &str) -> Result<(), ConfigError> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|err: toml::de::Error| ConfigError::Parse(err.message().to_string()))?;
+        for (key, value) in &table {
+            match (key.as_str(), value) {
+                ("display", toml::Value::Table(display)) => self.load_display(display)?,
+                ("keys", toml::Value::Table(keys)) => self.load_keys(keys)?,
+                ("controller", toml::Value::Table(controller)) => {
+                    self.load_controller(controller)?
+                }
+                _ => return Err(invalid(key, "is unknown")),
+            }
+        }
+        Ok(())
+    }
+    fn load_display(&mut self, table: &toml::Table) -> Result<(), ConfigError> {
+        // the theme goes first so the single colours can replace parts of it
+        if let Some(value) = table.get("theme") {
+            let name = value.as_str().unwrap_or_default();
+            self.palette = display::theme(name).ok_or_else(|| {
+                let names: Vec<_> = display::THEMES.iter().map(|(name, _)| *name).collect();
+                invalid(
+                    "display.theme",
+                    &format!("expects one of: {}", names.join(", ")),
+                )
+            })?;
+        }
+        for (key, value) in table {
+            let name = format!("display.{}", key);
+            match key.as_str() {
+                "theme" => {}
+                "foreground" | "background" => {
+                    let color = value
+                        .as_str()
+                        .and_then(display::parse_color)
+                        .ok_or_else(|| invalid(&name, "expects a colour like \"#FFB000\""))?;
+                    self.palette[(key == "foreground") as usize] = color;
+                }
+                "scale" => {
+                    let scale = value
+                        .as_float()
+                        .or_else(|| value.as_integer().map(|i| i as f64))
+                        .filter(|&scale| scale >= 1.0)
+                        .ok_or_else(|| invalid(&name, "expects a pixel size of at least 1"))?;
+                    self.display.scale = scale as f32;
+                }
+                "scaling" => {
+                    self.display.scaling = value
+                        .as_str()
+                        .and_then(Scaling::from_name)
+                        .ok_or_else(|| invalid(&name, "expects integer or fit"))?;
+                }
+                "fullscreen" | "resizable" | "grid" => {
+                    let flag = value
+                        .as_bool()
+                        .ok_or_else(|| invalid(&name, "expects true or false"))?;
+                    match key.as_str() {
+                        "fullscreen" => self.display.fullscreen = flag,
+                        "resizable" => self.display.resizable = flag,
+                        _ => self.display.grid = flag,
+                    }
+                }
+                _ => return Err(invalid(&name, "is unknown")),
+            }
+        }
+        Ok(())
+    }
+    fn load_keys(&mut self, table: &toml::Table) -> Result<(), ConfigError> {
+        if let Some(value) = table.get("preset") {
+            self.keymap = value
+                .as_str()
+                .and_then(Keymap::from_preset)
+                .ok_or_else(|| {
+                    invalid(
+                        "keys.preset",
+                        &format!("expects one of: {}", PRESETS.join(", ")),
+                    )
+                })?;
+        }
+        for (key, value) in table {
+            if key != "preset" {
+                bind(&mut self.keymap, "keys", key, value)?;
+            }
+        }
+        Ok(())
+    }
+    fn load_controller(&mut self, table: &toml::Table) -> Result<(), ConfigError> {
+        for (key, value) in table {
+            if key == "deadzone" {
+                self.stick_deadzone = value
+                    .as_integer()
+                    .and_then(|deadzone| u16::try_from(deadzone).ok())
+                    .filter(|&deadzone| deadzone < i16::MAX as u16)
+                    .ok_or_else(|| {
+                        invalid(
+                            "controller.deadzone",
+                            "expects a deflection from 0 to 32766",
+                        )
+                    })?;
+            } else {
+                bind(&mut self.controller, "controller", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Binds the host keys or buttons in `value` to the CHIP-8 key named by `key`.
+fn bind(
+    keymap: &mut Keymap,
+    section: &str,
+    key: &str,
+    value: &toml::Value,
+) -> Result<(), ConfigError> {
+    let name = format!("{}.{}", section, key);
+    let chip8_key = u8::from_str_radix(key, 16)
+        .ok()
+        .filter(|&k| k < 16)
+        .ok_or_else(|| invalid(&name, "is not a CHIP-8 key from 0 to F"))?;
+    let hosts = match value {
+        toml::Value::String(host) => Some(vec![host.as_str()]),
+        toml::Value::Array(hosts) => hosts.iter().map(toml::Value::as_str).collect(),
+        _ => None,
+    }
+    .ok_or_else(|| invalid(&name, "expects a name or a list of names"))?;
+    if section == "keys" {
+        if let Some((host, action)) = hosts.iter().find_map(|host| Some((host, hotkey(host)?))) {
+            return Err(invalid(
+                &name,
+                &format!("binds {}, which is the hotkey for {}", host, action),
+            ));
+        }
+    }
+    keymap.bind(chip8_key, &hosts);
+    Ok(())
+}
+
+fn invalid(key: &str, message: &str) -> ConfigError {
+    ConfigError::Invalid {
+        key: key.to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// `<rom>.toml` next to the ROM, for settings that only apply to it.
+pub fn rom_path(rom: &Path) -> PathBuf {
+    let mut path = rom.as_os_str().to_owned();
+    path.push(".toml");
+    PathBuf::from(path)
+}
+
+/// `$XDG_CONFIG_HOME/chip8`, or `~/.config/chip8` if that is unset.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join("chip8"))
+}
+
+/// `config.toml` in the [`config_dir`].
+pub fn default_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// `database` in the [`config_dir`], a ROM database whose entries replace the bundled ones.
+pub fn database_dir() -> Option<PathBuf> {
+    Some(config_dir()?.join("database"))
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new(Platform::default())
+    }
+}
@@ -0,0 +1,163 @@
+//! Runs the bundled test ROMs headless and compares the final frame against known good results.
+
+use std::{fs, path::Path};
+
+use crate::{
+    config::Config,
+    database::{self, Database},
+    emulator::{Emulator, KeyState},
+    platform::Platform,
+    prep_buffer,
+};
+
+/// A ROM from the `roms/` directory together with its input script and expected result, it runs with the quirks
+/// the bundled database gives it.
+pub struct Case {
+    pub name: &'static str,
+    /// File name inside the ROM directory.
+    pub rom: &'static str,
+    pub platform: Platform,
+    /// Frames to run before the framebuffer is compared.
+    pub frames: u32,
+    /// Keys held from the first frame up to, but not including, the second one, as a key mask.
+    pub keys: &'static [(u32, u32, u16)],
+    /// [`Graphics::digest`](crate::emulator::Graphics::digest) of the final frame.
+    pub golden: &'static str,
+}
+
+impl Case {
+    fn keys_at(&self, frame: u32) -> u16 {
+        self.keys
+            .iter()
+            .filter(|(from, to, _)| (*from..*to).contains(&frame))
+            .fold(0, |mask, (_, _, keys)| mask | keys)
+    }
+}
+
+pub const CASES: [Case; 6] = [
+    Case {
+        name: "chip8 logo",
+        rom: "1-chip8-logo.ch8",
+        platform: Platform::Chip8,
+        frames: 60,
+        keys: &[],
+        golden: "0190bcebf1d80cd8e5430625adc2c45dc2eba73e",
+    },
+    Case {
+        name: "corax+ opcodes",
+        rom: "3-corax+.ch8",
+        platform: Platform::Chip8,
+        frames: 60,
+        keys: &[],
+        golden: "31a30c4ce319f500cac06e20f831114eb0716d81",
+    },
+    Case {
+        name: "keypad EX9E down",
+        rom: "6-keypad.ch8",
+        platform: Platform::Chip8,
+        frames: 150,
+        // choose the first test once the menu is drawn, then hold 5 and A
+        keys: &[(60, 65, 1 << 0x1), (100, 150, 1 << 0x5 | 1 << 0xA)],
+        golden: "7e253d401bd52b066d3eb10cace2870edcf353b8",
+    },
+    Case {
+        name: "fishie",
+        rom: "Fishie [Hap, 2005].ch8",
+        platform: Platform::Chip8,
+        frames: 30,
+        keys: &[],
+        golden: "caf625afdf7b7a1858c13b5a3039c68295583207",
+    },
+    Case {
+        name: "keypad test",
+        rom: "Keypad Test [Hap, 2006].ch8",
+        platform: Platform::Chip8,
+        // press and release 7, the run ends while it blinks
+        frames: 220,
+        keys: &[(200, 210, 1 << 0x7)],
+        golden: "14cac0ce5346122d5cf33a097d83da6a4b407064",
+    },
+    Case {
+        name: "test opcode",
+        rom: "test_opcode.ch8",
+        platform: Platform::Chip8,
+        frames: 60,
+        keys: &[],
+        golden: "cfc3df7ae08da40fa2b505aa837b0e9397231a6b",
+    },
+];
+
+/// Outcome of one case, `actual` holds the digest of the final frame or why the ROM could not be run.
+pub struct CaseResult {
+    pub name: &'static str,
+    pub expected: &'static str,
+    pub actual: Result<String, String>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.actual.as_deref() == Ok(self.expected)
+    }
+}
+
+/// Runs the case with the ROM from `roms` and returns the final emulator.
+pub fn run_case(case: &Case, roms: &Path) -> Result<Emulator, String> {
+    let path = roms.join(case.rom);
+    let rom = fs::read(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let memory_size = case.platform.memory_size();
+    if rom.len() > memory_size - 0x200 {
+        return Err(format!("{}: program file too large", path.display()));
+    }
+    let profile = Database::bundled().lookup(&database::hash(&rom));
+    let mut buffer = rom;
+    buffer.resize(memory_size, 0);
+    prep_buffer(&mut buffer);
+
+    let mut config = Config::new(case.platform);
+    if let Some((_, quirks)) = profile.and_then(|profile| profile.platform) {
+        config.quirks = quirks;
+    }
+    config.seed = Some(0);
+    let mut emulator = Emulator::with_config(&config);
+    emulator.load(&buffer);
+    for frame in 0..case.frames {
+        if emulator.exited {
+            break;
+        }
+        emulator.key_buffer = KeyState::from_mask(case.keys_at(frame));
+        emulator.run_frame().map_err(|err| err.to_string())?;
+    }
+    Ok(emulator)
+}
+
+pub fn run_all(roms: &Path) -> Vec<CaseResult> {
+    CASES
+        .iter()
+        .map(|case| CaseResult {
+            name: case.name,
+            expected: case.golden,
+            actual: run_case(case, roms).map(|emulator| emulator.graphics.digest()),
+        })
+        .collect()
+}
+
+/// Prints a pass/fail table and returns true if every case passed.
+pub fn print_results(results: &[CaseResult]) -> bool {
+    println!("{:<20} {:<6} Final frame", "ROM", "Result");
+    for result in results {
+        let status = if result.passed() { "pass" } else { "FAIL" };
+        match &result.actual {
+            Ok(digest) if result.passed() => {
+                println!("{:<20} {:<6} {}", result.name, status, digest)
+            }
+            Ok(digest) => println!(
+                "{:<20} {:<6} {} (expected {})",
+                result.name, status, digest, result.expected
+            ),
+            Err(err) => println!("{:<20} {:<6} {}", result.name, status, err),
+        }
+    }
+    let passed = results.iter().filter(|r| r.passed()).count();
+    println!("{} of {} passed", passed, results.len());
+    passed == results.len()
+}
@@ -1,23 +1,21 @@
-use sdl2::keyboard::Keycode;
-
-pub const SLEEP_MICROS: u64 = 1500;
+/// Speed multipliers applied while the fast-forward and slow-motion hotkeys are held.
+pub const FAST_FORWARD: f32 = 4.0;
+pub const SLOW_MOTION: f32 = 0.25;
+/// Seconds of frames kept for rewinding by default.
+pub const REWIND_SECONDS: u32 = 10;
+pub const STACK_SIZE: usize = 16;
 pub const FONT_BASE_ADDRESS: usize = 0x050;
-
-pub const KEYS: [Keycode; 16] = [
-    Keycode::Num1,
-    Keycode::Num2,
-    Keycode::Num3,
-    Keycode::Num4,
-    Keycode::Q,
-    Keycode::W,
-    Keycode::E,
-    Keycode::R,
-    Keycode::A,
-    Keycode::S,
-    Keycode::D,
-    Keycode::F,
-    Keycode::Y,
-    Keycode::X,
-    Keycode::C,
-    Keycode::V,
-];
+pub const BIG_FONT_BASE_ADDRESS: usize = 0x0A0;
+pub const AUDIO_SAMPLE_RATE: i32 = 44100;
+/// Time the buzzer takes to fade in and out.
+pub const AUDIO_RAMP_SECONDS: f32 = 0.005;
+/// Colours for the four combinations of the two XO-CHIP bitplanes: black, white, light and dark grey.
+pub const PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]];
+/// Size of a low resolution pixel in the window by default.
+pub const WINDOW_SCALE: f32 = 10.0;
+/// Stick deflection, out of 32767, below which the stick counts as centered.
+pub const STICK_DEADZONE: u16 = 8000;
+/// Screenshots are scaled to the window size by default.
+pub const SCREENSHOT_SCALE: u32 = 10;
+/// Size of a low resolution pixel in recorded videos by default.
+pub const VIDEO_SCALE: u32 = 10;
@@ -0,0 +1,235 @@
+//! Settings of known ROMs, looked up by the SHA-1 of the ROM file in a database laid out like the community
+//! chip-8-database (<https://github.com/chip-8/chip-8-database>).
+//!
+//! A database is a directory holding two JSON files:
+//!
+//! - `sha1-hashes.json` maps the SHA-1 of a ROM file, in lower case hex, to the index of its program in
+//!   `programs.json`.
+//! - `programs.json` is an array of programs. Each has a `title` and a `roms` object with the settings of every
+//!   known ROM of the program, keyed by SHA-1. All settings are optional:
+//!
+//! ```text
+//! {
+//!   "platforms": ["superchip", "xochip"],                 preferred platform first
+//!   "quirkyPlatforms": { "superchip": { "shift": false } }, quirks that differ from the platform's
+//!   "tickrate": 30,                                       instructions per frame
+//!   "colors": { "pixels": ["#000000", "#FFFFFF"] },       background, foreground and the XO-CHIP planes
+//!   "keys": { "up": 5, "left": 7, "right": 9, "a": 6 }    CHIP-8 keys of the game's controls
+//! }
+//! ```
+//!
+//! The platforms `originalChip8`, `hybridVIP`, `modernChip8`, `chip48`, `superchip1`, `superchip` and `xochip` are
+//! understood, the quirks `shift`, `memoryIncrementByX`, `memoryLeaveIUnchanged`, `wrap`, `jump`, `vblank` and
+//! `logic` too. Other fields are ignored.
+//!
+//! The controls `up`, `down`, `left`, `right`, `a` and `b` are put on the arrow keys, Space and Return and on the
+//! d-pad and the A and B buttons of a controller, in addition to the keymap.
+//!
+//! A database for the bundled ROMs is built in. Entries of a database in the user's config directory take
+//! precedence, see [`crate::config::database_dir`].
+
+use std::{fs, path::Path};
+
+use serde_json::{Map, Value};
+
+use crate::{
+    config::{self, Config},
+    display,
+    error::DatabaseError,
+    platform::Platform,
+    quirks::{IndexIncrement, Quirks},
+};
+
+const BUNDLED_HASHES: &str = include_str!("../database/sha1-hashes.json");
+const BUNDLED_PROGRAMS: &str = include_str!("../database/programs.json");
+
+/// Controls named by the database, the host key and the controller button they go on.
+const CONTROLS: [(&str, &str, &str); 6] = [
+    ("up", "Up", "dpup"),
+    ("down", "Down", "dpdown"),
+    ("left", "Left", "dpleft"),
+    ("right", "Right", "dpright"),
+    ("a", "Space", "a"),
+    ("b", "Return", "b"),
+];
+
+/// What the database knows about a ROM.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    pub title: String,
+    /// The first platform of the entry this emulator supports, with its quirks.
+    pub platform: Option<(Platform, Quirks)>,
+    pub instructions_per_frame: Option<u32>,
+    /// Colours replacing the first entries of the palette.
+    pub colors: Vec<[u8; 3]>,
+    /// Controls of the game like `up` or `a`, and the CHIP-8 key they press.
+    pub keys: Vec<(String, u8)>,
+}
+
+impl Profile {
+    /// Applies the settings to `config`, the title is shown in the window.
+    pub fn apply(&self, config: &mut Config) {
+        config.title = Some(self.title.clone());
+        if let Some((platform, quirks)) = self.platform {
+            config.set_platform(platform);
+            config.quirks = quirks;
+        }
+        if let Some(ipf) = self.instructions_per_frame {
+            config.instructions_per_frame = ipf;
+        }
+        for (entry, color) in config.palette.iter_mut().zip(&self.colors) {
+            *entry = *color;
+        }
+        for (control, key) in &self.keys {
+            if let Some(&(_, host, button)) = CONTROLS.iter().find(|(name, ..)| name == control) {
+                config.keymap.add(*key, host);
+                config.controller.add(*key, button);
+            }
+        }
+    }
+}
+
+pub struct Database {
+    /// SHA-1 of a ROM file and the index of its program.
+    hashes: Map<String, Value>,
+    programs: Vec<Value>,
+}
+
+impl Database {
+    /// The database of the bundled ROMs.
+    pub fn bundled() -> Self {
+        Self::from_json(BUNDLED_HASHES, BUNDLED_PROGRAMS).expect("bundled database should be valid")
+    }
+    /// Reads `sha1-hashes.json` and `programs.json` from `dir`.
+    pub fn load_dir(dir: &Path) -> Result<Self, DatabaseError> {
+        let hashes = fs::read_to_string(dir.join("sha1-hashes.json"))?;
+        let programs = fs::read_to_string(dir.join("programs.json"))?;
+        Self::from_json(&hashes, &programs)
+    }
+    pub fn from_json(hashes: &str, programs: &str) -> Result<Self, DatabaseError> {
+        let parse = |file: &str, text: &str| {
+            serde_json::from_str::<Value>(text)
+                .map_err(|err| DatabaseError::Parse(format!("{}: {}", file, err)))
+        };
+        let Value::Object(hashes) = parse("sha1-hashes.json", hashes)? else {
+            return Err(DatabaseError::Invalid(String::from(
+                "sha1-hashes.json should be an object of hashes and program indices",
+            )));
+        };
+        let Value::Array(programs) = parse("programs.json", programs)? else {
+            return Err(DatabaseError::Invalid(String::from(
+                "programs.json should be an array of programs",
+            )));
+        };
+        Ok(Database { hashes, programs })
+    }
+    /// Profile of the ROM with the SHA-1 `hash`, given in hex.
+    pub fn lookup(&self, hash: &str) -> Option<Profile> {
+        let hash = hash.to_lowercase();
+        let index = self.hashes.get(&hash)?.as_u64()?;
+        let program = self.programs.get(index as usize)?;
+        let rom = &program["roms"][&hash];
+        Some(Profile {
+            title: program["title"].as_str().unwrap_or_default().to_string(),
+            platform: rom["platforms"].as_array().and_then(|platforms| {
+                platforms.iter().find_map(|id| {
+                    let id = id.as_str()?;
+                    let (platform, quirks) = platform(id)?;
+                    Some((platform, quirky(quirks, &rom["quirkyPlatforms"][id])))
+                })
+            }),
+            instructions_per_frame: rom["tickrate"]
+                .as_u64()
+                .and_then(|ipf| u32::try_from(ipf).ok())
+                .filter(|&ipf| ipf > 0),
+            colors: rom["colors"]["pixels"]
+                .as_array()
+                .map(|pixels| {
+                    pixels
+                        .iter()
+                        .filter_map(|color| display::parse_color(color.as_str()?))
+                        .take(4)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            keys: rom["keys"]
+                .as_object()
+                .map(|keys| {
+                    keys.iter()
+                        .filter_map(|(control, key)| {
+                            let key = key.as_u64().filter(|&key| key < 16)?;
+                            Some((control.clone(), key as u8))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// SHA-1 of a ROM file as lower case hex, the key of the database.
+pub fn hash(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+/// Looks the ROM up in the user's database and then in the bundled one.
+pub fn lookup(rom: &[u8]) -> Result<Option<Profile>, DatabaseError> {
+    let hash = hash(rom);
+    if let Some(dir) = config::database_dir().filter(|dir| dir.exists()) {
+        if let Some(profile) = Database::load_dir(&dir)?.lookup(&hash) {
+            return Ok(Some(profile));
+        }
+    }
+    Ok(Database::bundled().lookup(&hash))
+}
+
+/// Platform of a database platform id and its quirks.
+fn platform(id: &str) -> Option<(Platform, Quirks)> {
+    match id {
+        "originalChip8" | "hybridVIP" => Some((Platform::Chip8, Quirks::vip())),
+        "modernChip8" => Some((
+            Platform::Chip8,
+            Quirks {
+                vf_reset: false,
+                display_wait: false,
+                ..Quirks::vip()
+            },
+        )),
+        "chip48" => Some((Platform::Chip8, Quirks::chip48())),
+        "superchip1" | "superchip" => Some((Platform::SuperChip, Quirks::schip())),
+        "xochip" => Some((Platform::XoChip, Quirks::xochip())),
+        _ => None,
+    }
+}
+
+/// Applies the quirks of a `quirkyPlatforms` entry, the database names them after the modern behaviour.
+fn quirky(mut quirks: Quirks, overrides: &Value) -> Quirks {
+    let Some(overrides) = overrides.as_object() else {
+        return quirks;
+    };
+    for (name, value) in overrides {
+        let Some(flag) = value.as_bool() else {
+            continue;
+        };
+        match name.as_str() {
+            "shift" => quirks.shift_uses_vy = !flag,
+            "memoryIncrementByX" | "memoryLeaveIUnchanged" => {
+                let increment = match name.as_str() {
+                    "memoryIncrementByX" => IndexIncrement::X,
+                    _ => IndexIncrement::None,
+                };
+                if flag {
+                    quirks.index_increment = increment;
+                } else if quirks.index_increment == increment {
+                    quirks.index_increment = IndexIncrement::XPlusOne;
+                }
+            }
+            "wrap" => quirks.clip_sprites = !flag,
+            "jump" => quirks.jump_uses_vx = flag,
+            "vblank" => quirks.display_wait = flag,
+            "logic" => quirks.vf_reset = flag,
+            _ => {}
+        }
+    }
+    quirks
+}
@@ -1,70 +1,49 @@
-use std::io::Write;
-
-use sdl2::{event::Event, keyboard::Keycode};
+use std::{
+    io::Write,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    consts::KEYS,
+    config::Config,
+    consts::{PALETTE, SCREENSHOT_SCALE},
     disassembler::Disassembler,
-    emulator::{Display, Emulator},
+    emulator::Emulator,
+    frontend::{Frontend, Hotkey},
+    instruction::Syntax,
+    savestate, screenshot,
+    trace::Tracer,
 };
 
-pub fn debug(program: &[u8]) {
-    let sdl_context = sdl2::init().expect("sdl2 should initialize");
-
-    let timer = sdl_context
-        .timer()
-        .expect("sdl2 context should have a timer");
-    let mut display = Display::new(&sdl_context);
-    let mut event_pump = sdl_context
-        .event_pump()
-        .expect("sdl2 context should have an event pump");
-
-    let mut emulator = Emulator::new();
+pub fn debug(program: &[u8], config: &Config, frontend: &mut dyn Frontend) {
+    let mut emulator = Emulator::with_config(config);
     emulator.load(program);
-    display.canvas.present();
+    emulator.tracer = Tracer::from_config(config);
 
     let mut debugger = Debugger::new();
-    debugger.attach(&mut emulator, &mut display, &mut event_pump, &timer);
+    debugger.rom_path = config.rom_path.clone();
+    debugger.palette = config.palette;
+    debugger.screenshot_scale = config.screenshot_scale;
+    debugger.syntax = config.syntax;
+    debugger.attach(&mut emulator, frontend);
 }
 fn handle_loop(
-    event_pump: &mut sdl2::EventPump,
-    _timer: &sdl2::TimerSubsystem,
+    frontend: &mut dyn Frontend,
+    start: &Instant,
+    next: &mut u64,
     emulator: &mut Emulator,
 ) -> bool {
-    let _next: u64 = 0;
-    for event in event_pump.poll_iter() {
-        match event {
-            Event::Quit { .. }
-            | Event::KeyDown {
-                keycode: Some(Keycode::Escape),
-                ..
-            } => return true,
-            Event::KeyDown {
-                keycode: Some(keycode),
-                ..
-            } => {
-                let key = KEYS.iter().position(|&x| x == keycode);
-                if let Some(k) = key {
-                    emulator.key_buffer.key = Some(k as u8);
-                }
-            }
-            Event::KeyUp {
-                keycode: Some(keycode),
-                ..
-            } => {
-                let key = KEYS.iter().position(|&x| x == keycode);
-                if let Some(key) = key {
-                    if let Some(k) = emulator.key_buffer.key {
-                        if k == key as u8 {
-                            emulator.key_buffer.key = None;
-                        }
-                    }
-                }
-            }
-            _ => {}
-        }
+    let now = start.elapsed().as_millis() as u64;
+    if now >= *next {
+        frontend.present(&emulator.graphics);
+        emulator.graphics.mark_clean();
+        frontend.set_playing(emulator.sound());
+        *next = now + 1000 / 60;
     }
-    false
+    frontend
+        .poll(&mut emulator.key_buffer)
+        .contains(&Hotkey::Quit)
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -78,6 +57,13 @@ enum State {
 pub struct Debugger {
     state: State,
     breakpoints: Vec<u16>,
+    /// Path of the ROM, numbered save state slots are stored next to it.
+    pub rom_path: Option<PathBuf>,
+    /// Colours and pixel size of screenshots.
+    pub palette: [[u8; 3]; 4],
+    pub screenshot_scale: u32,
+    /// How the x command writes instructions.
+    pub syntax: Syntax,
 }
 
 impl Debugger {
@@ -85,19 +71,50 @@ impl Debugger {
         Debugger {
             state: State::Stopped,
             breakpoints: Vec::new(),
+            rom_path: None,
+            palette: PALETTE,
+            screenshot_scale: SCREENSHOT_SCALE,
+            syntax: Syntax::default(),
+        }
+    }
+    /// Resolves the argument of save/load, a slot number (0 if empty) or a file name.
+    fn state_path(&self, arg: &str) -> Option<PathBuf> {
+        if arg.is_empty() || arg.parse::<u8>().is_ok() {
+            let slot = arg.parse::<u8>().unwrap_or(0);
+            self.rom_path
+                .as_ref()
+                .map(|rom| savestate::slot_path(rom, slot))
+        } else {
+            Some(PathBuf::from(arg))
         }
     }
-    pub fn attach(
-        &mut self,
-        emulator: &mut Emulator,
-        display: &mut Display,
-        event_pump: &mut sdl2::EventPump,
-        timer: &sdl2::TimerSubsystem,
-    ) {
+    /// Runs the rest of the current frame like [`Emulator::run_frame`], stopping at errors, when the program exits
+    /// and before a breakpoint. The instruction at the PC runs without checking for a breakpoint.
+    pub fn continue_frame(&mut self, emulator: &mut Emulator) {
+        loop {
+            if let Err(err) = emulator.step() {
+                println!("{}", err);
+                self.state = State::Paused;
+                return;
+            }
+            if emulator.frame_cycle == 0 || emulator.exited {
+                return;
+            }
+            if self.breakpoints.contains(&(emulator.pc as u16)) {
+                self.state = State::HitBreackpoint;
+                return;
+            }
+        }
+    }
+    pub fn attach(&mut self, emulator: &mut Emulator, frontend: &mut dyn Frontend) {
         let mut skip = false;
+        let start = Instant::now();
+        let mut next: u64 = 0;
+        let frame = Duration::from_secs(1) / 60;
+        let mut next_frame = Instant::now();
         self.state = State::Paused;
         loop {
-            if handle_loop(event_pump, timer, emulator) {
+            if handle_loop(frontend, &start, &mut next, emulator) {
                 break;
             }
             if !skip
@@ -111,18 +128,39 @@ impl Debugger {
             } else {
                 skip = false;
             }
+            if emulator.exited && self.state != State::Paused && self.state != State::Stopped {
+                println!("Program exited at 0x{:x}", emulator.pc);
+                self.state = State::Paused;
+            }
             match self.state {
                 State::Running(None) => {
-                    emulator.run(Some(display));
+                    self.continue_frame(emulator);
+                    // sleep once per frame, without trying to catch up after a stall
+                    next_frame += frame;
+                    let now = Instant::now();
+                    if next_frame > now {
+                        thread::sleep(next_frame - now);
+                    } else {
+                        next_frame = now;
+                    }
                 }
                 State::Running(Some(0)) => {
+                    if emulator.sound_timer > 0 {
+                        // let a step be heard for one frame before waiting for input
+                        frontend.set_playing(emulator.sound());
+                        thread::sleep(Duration::from_secs(1) / 60);
+                    }
                     self.state = State::Paused;
                 }
                 State::Running(Some(n)) => {
                     self.state = State::Running(Some(n - 1));
-                    emulator.run(Some(display));
+                    if let Err(err) = emulator.step() {
+                        println!("{}", err);
+                        self.state = State::Paused;
+                    }
                 }
                 State::Paused => {
+                    frontend.set_playing(None);
                     print!(">");
                     std::io::stdout().flush().unwrap();
                     let mut input = String::new();
@@ -131,39 +169,40 @@ impl Debugger {
                     if input.is_empty() {
                         continue;
                     }
-                    let cmd = input.chars().next().unwrap();
+                    let (cmd, arg) = input.split_once(' ').unwrap_or((input, ""));
+                    let arg = arg.trim();
                     match cmd {
-                        's' => {
-                            let n = input[1..].trim().parse::<u32>().unwrap_or(1);
+                        "s" => {
+                            let n = arg.parse::<u32>().unwrap_or(1);
                             self.state = State::Running(Some(n));
                             skip = true;
                         }
-                        'c' => {
+                        "c" => {
                             self.state = State::Running(None);
                             skip = true;
                         }
-                        'b' => {
-                            let addr = u16::from_str_radix(input[1..].trim(), 16);
+                        "b" => {
+                            let addr = u16::from_str_radix(arg, 16);
                             if let Ok(addr) = addr {
                                 self.breakpoints.push(addr);
                             } else {
                                 println!("Invalid address");
                             }
                         }
-                        'd' => {
-                            let addr = u16::from_str_radix(input[1..].trim(), 16);
+                        "d" => {
+                            let addr = u16::from_str_radix(arg, 16);
                             if let Ok(addr) = addr {
                                 self.breakpoints.retain(|&x| x != addr);
                             } else {
                                 println!("Invalid address");
                             }
                         }
-                        'l' => {
+                        "l" => {
                             for (i, &addr) in self.breakpoints.iter().enumerate() {
                                 println!("{}: 0x{:x}", i, addr);
                             }
                         }
-                        'p' => {
+                        "p" => {
                             println!("PC: 0x{:X}", emulator.pc);
                             println!("I:  0x{:X}", emulator.index);
                             println!(
@@ -196,23 +235,69 @@ impl Debugger {
                             );
                             println!("Stack: {:?}", emulator.stack);
                         }
-                        'x' => {
-                            let addr = u16::from_str_radix(input[1..].trim(), 16);
+                        "x" => {
+                            let addr = u16::from_str_radix(arg, 16);
                             if let Ok(addr) = addr {
-                                if addr >= 4096 {
+                                if addr as usize + 1 >= emulator.memory.len() {
                                     println!("Invalid address");
                                     continue;
                                 }
-                                let dis = Disassembler::new();
-                                let opcode = u16::from(emulator.memory[addr as usize]) << 8
-                                    | u16::from(emulator.memory[(addr + 1) as usize]);
-
-                                println!("{}", dis.disassemble_opcode(opcode));
+                                let mut dis =
+                                    Disassembler::with_platform(emulator.platform, emulator.quirks);
+                                dis.syntax = self.syntax;
+                                // F000 is followed by its address, which may be past the end of memory
+                                let word = |address: usize| {
+                                    let byte = |address: usize| {
+                                        u16::from(
+                                            emulator.memory.get(address).copied().unwrap_or(0),
+                                        )
+                                    };
+                                    byte(address) << 8 | byte(address + 1)
+                                };
+                                let addr = addr as usize;
+                                println!(
+                                    "{}",
+                                    dis.disassemble_with_operand(word(addr), word(addr + 2))
+                                );
                             } else {
                                 println!("Invalid address");
                             }
                         }
-                        'h' => {
+                        "save" | "load" => {
+                            let Some(path) = self.state_path(arg) else {
+                                println!("Expected a slot number or a file");
+                                continue;
+                            };
+                            let result = if cmd == "save" {
+                                savestate::save_file(emulator, &path)
+                            } else {
+                                savestate::load_file(emulator, &path)
+                            };
+                            match result {
+                                Ok(()) => println!("{} {}", cmd, path.display()),
+                                Err(err) => println!("{}", err),
+                            }
+                        }
+                        "shot" => {
+                            let path = match (arg, &self.rom_path) {
+                                ("", Some(rom)) => screenshot::next_path(rom),
+                                ("", None) => {
+                                    println!("Expected a file");
+                                    continue;
+                                }
+                                (file, _) => PathBuf::from(file),
+                            };
+                            match screenshot::save(
+                                &emulator.graphics,
+                                &path,
+                                &self.palette,
+                                self.screenshot_scale,
+                            ) {
+                                Ok(()) => println!("Saved screenshot to {}", path.display()),
+                                Err(err) => println!("Could not save screenshot: {}", err),
+                            }
+                        }
+                        "h" => {
                             println!("------------------- HELP -------------------");
                             println!("s        - step for 1 instruction");
                             println!("s [n]    - step for n instructions");
@@ -221,10 +306,13 @@ impl Debugger {
                             println!("l        - list breakpoints");
                             println!("p        - print registers and memory");
                             println!("x [addr] - disassemble instruction at addr");
+                            println!("save [n] - save the machine to slot n or a file");
+                            println!("load [n] - restore the machine from slot n or a file");
+                            println!("shot [f] - save a screenshot as .png, .pbm or .ppm");
                             println!("c        - continue");
                             println!("q        - quit");
                         }
-                        'q' => {
+                        "q" => {
                             self.state = State::Stopped;
                         }
                         _ => {
@@ -1,190 +1,312 @@
-pub fn disassemble(program: &[u8]) {
-    let mut disassembler = Disassembler::new();
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    config::Config,
+    instruction::{Flow, Instruction, Syntax},
+    platform::Platform,
+    quirks::Quirks,
+};
+
+/// Programs are loaded to and start at 0x200.
+const START: usize = 0x200;
+
+pub fn disassemble(program: &[u8], config: &Config) {
+    let mut disassembler = Disassembler::with_config(config);
     disassembler.load(program);
     disassembler.disassemble();
 }
 
 pub struct Disassembler {
-    pub memory: [u8; 4096],
+    pub memory: Vec<u8>,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub syntax: Syntax,
+}
+
+impl Default for Disassembler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Disassembler {
     pub fn new() -> Disassembler {
-        Disassembler { memory: [0; 4096] }
+        Self::with_quirks(Quirks::default())
+    }
+    pub fn with_quirks(quirks: Quirks) -> Disassembler {
+        Self::with_platform(Platform::Chip8, quirks)
+    }
+    pub fn with_platform(platform: Platform, quirks: Quirks) -> Disassembler {
+        Disassembler {
+            memory: vec![0; platform.memory_size()],
+            platform,
+            quirks,
+            syntax: Syntax::default(),
+        }
+    }
+    /// Uses the platform, quirks and syntax of `config`.
+    pub fn with_config(config: &Config) -> Disassembler {
+        Disassembler {
+            syntax: config.syntax,
+            ..Self::with_platform(config.platform, config.quirks)
+        }
     }
     pub fn load(&mut self, program: &[u8]) {
-        self.memory = program.try_into().expect("Program should be 4096 bytes");
+        assert!(
+            program.len() == self.memory.len(),
+            "Program should be {} bytes",
+            self.memory.len()
+        );
+        self.memory.copy_from_slice(program);
     }
+    /// Prints the program with labels, see [`Disassembler::listing`].
     pub fn disassemble(&self) {
-        for i in (512..4096).step_by(2) {
-            let opcode = (self.memory[i] as u16) << 8 | self.memory[i + 1] as u16;
-            if opcode == 0x0000 {
-                continue;
-            }
-            println!(
-                "{:04X}: {:04X} {}",
-                i - 512,
-                opcode,
-                self.disassemble_opcode(opcode)
-            );
+        for line in self.listing() {
+            println!("{}", line);
         }
     }
-    pub fn disassemble_opcode(&self, opcode: u16) -> String {
-        let instr = (opcode & 0xF000) >> 12;
-        let x = (opcode & 0x0F00) >> 8;
-        let y = (opcode & 0x00F0) >> 4;
-        let n = opcode & 0x000F;
-        let nn = opcode & 0x00FF;
-        let nnn = opcode & 0x0FFF;
-        match instr {
-            0x0 => {
-                match nnn {
-                    0x0E0 => {
-                        // clear screen
-                        "clear screen".to_owned()
-                    }
-                    0x0EE => {
-                        // return from subroutine
-                        "return from subroutine".to_owned()
+    /// Follows the program from 0x200 through jumps, calls and skips to tell code from data.
+    ///
+    /// Code only reached through computed jumps is missed, apart from the jumps of a BNNN jump table.
+    pub fn analyze(&self) -> Analysis {
+        let mut code = BTreeMap::new();
+        let (mut calls, mut jumps, mut data) = (BTreeSet::new(), BTreeSet::new(), BTreeSet::new());
+        let mut pending = vec![START];
+        while let Some(address) = pending.pop() {
+            if code.contains_key(&address) {
+                continue;
+            }
+            let Some(instruction) = self.instruction_at(address) else {
+                continue;
+            };
+            code.insert(address, instruction);
+            let next = address + instruction.size();
+            match instruction.flow() {
+                Flow::Next => pending.push(next),
+                Flow::Skip => {
+                    pending.push(next);
+                    pending.push(next + self.size_at(next));
+                }
+                Flow::Jump(target) => {
+                    jumps.insert(target as usize);
+                    pending.push(target as usize);
+                }
+                Flow::Call(target) => {
+                    calls.insert(target as usize);
+                    pending.extend([target as usize, next]);
+                }
+                Flow::JumpTable(base) => {
+                    // the entries of a table are usually jumps, one after the other
+                    jumps.insert(base as usize);
+                    let mut entry = base as usize;
+                    while let Some(Instruction::Jump(_)) = self.instruction_at(entry) {
+                        pending.push(entry);
+                        entry += 2;
                     }
-                    _ => "unknown".to_owned(),
                 }
+                Flow::Stop => {}
             }
-            0x1 => {
-                // jump
-                format!("jump to 0x{:03X}", nnn)
-            }
-            0x2 => {
-                // call subroutine
-                format!("call subroutine at 0x{:03X}", nnn)
-            }
-            0x3 => {
-                // skip next instruction if Vx == nn
-                format!("skip next instruction if V{:X} == 0x{:02x}", x, nn)
-            }
-            0x4 => {
-                // skip next instruction if Vx != nn
-                format!("skip next instruction if V{:X} != 0x{:02x}", x, nn)
-            }
-            0x5 => {
-                // skip next instruction if Vx == Vy
-                format!("skip next instruction if V{:X} == V{:X}", x, y)
+            match instruction {
+                Instruction::SetIndex(target) => {
+                    data.insert(target as usize);
+                }
+                Instruction::LongIndex => {
+                    data.insert(self.word(address + 2) as usize);
+                }
+                _ => {}
             }
-            0x6 => {
-                // set Vx to nn
-                format!("set V{:X} to 0x{:02x}", x, nn)
+        }
+
+        // every address gets one name, calls take precedence over jumps and jumps over data
+        let mut labels = BTreeMap::new();
+        let end = self.end(&code);
+        let names = [("sub", calls), ("label", jumps), ("data", data)];
+        for (prefix, addresses) in names {
+            for address in addresses {
+                if (START..end).contains(&address) {
+                    labels
+                        .entry(address)
+                        .or_insert_with(|| format!("{}_{:03X}", prefix, address));
+                }
             }
-            0x7 => {
-                // add nn to Vx
-                format!("add 0x{:02x} to V{:X}", nn, x)
+        }
+        Analysis { code, labels, end }
+    }
+    /// Lines of the disassembly: labels, instructions with their address and opcode, and the bytes that are not
+    /// code as `db` lines of up to 8 bytes, or Octo byte literals. Octo gets the address and opcode as a comment
+    /// after the statement, so the listing assembles.
+    ///
+    /// Labels and code reached inside an instruction that is already listed follow it as comments.
+    pub fn listing(&self) -> Vec<String> {
+        let Analysis { code, labels, end } = self.analyze();
+        let mut lines = Vec::new();
+        let mut address = START;
+        while address < end {
+            if let Some(label) = labels.get(&address) {
+                lines.push(self.label_line(label));
             }
-            0x8 => {
-                match (opcode & 0x000F) as u8 {
-                    0x0 => {
-                        // set Vx to Vy
-                        format!("set V{:X} to V{:X}", x, y)
-                    }
-                    0x1 => {
-                        // set Vx to Vx | Vy
-                        format!("set V{:X} to V{:X} | V{:X}", x, x, y)
-                    }
-                    0x2 => {
-                        // set Vx to Vx & Vy
-                        format!("set V{:X} to V{:X} & V{:X}", x, x, y)
-                    }
-                    0x3 => {
-                        // set Vx to Vx ^ Vy
-                        format!("set V{:X} to V{:X} ^ V{:X}", x, x, y)
-                    }
-                    0x4 => {
-                        // add Vx to Vy
-                        format!("add V{:X} to V{:X}", x, y)
-                    }
-                    0x5 => {
-                        // subtract Vy from Vx
-                        format!("subtract V{:X} from V{:X}", y, x)
-                    }
-                    0x6 => {
-                        // TODO: configure this to be optional
-                        format!("shift V{:X} right", x)
-                    }
-                    0x7 => {
-                        // subtract Vx from Vy
-                        format!("subtract V{:X} from V{:X}", x, y)
+            if let Some(instruction) = code.get(&address) {
+                lines.push(self.code_line(address, instruction, &labels));
+                let next = address + instruction.size();
+                for inside in address + 1..next {
+                    if let Some(label) = labels.get(&inside) {
+                        lines.push(format!("{} {}", self.comment(), self.label_line(label)));
                     }
-                    0xE => {
-                        //TODO: configure this to be optional
-                        format!("shift V{:X} left", x)
+                    if let Some(overlap) = code.get(&inside) {
+                        lines.push(format!(
+                            "{} {} (overlaps 0x{:04X})",
+                            self.comment(),
+                            self.code_line(inside, overlap, &labels),
+                            address
+                        ));
                     }
-                    _ => "unknown".to_owned(),
                 }
+                address = next;
+            } else {
+                // data runs up to the next instruction or label
+                let stop = (address + 1..end)
+                    .find(|next| code.contains_key(next) || labels.contains_key(next))
+                    .unwrap_or(end)
+                    .min(address + 8);
+                let bytes: Vec<_> = self.memory[address..stop]
+                    .iter()
+                    .map(|byte| format!("0x{:02X}", byte))
+                    .collect();
+                lines.push(match self.syntax {
+                    Syntax::Octo => format!("{:<24} # {:04X}", bytes.join(" "), address),
+                    _ => format!("{:04X}: db {}", address, bytes.join(", ")),
+                });
+                address = stop;
             }
-            0x9 => {
-                // skip next instruction if Vx != Vy
-                format!("skip next instruction if V{:X} != V{:X}", x, y)
-            }
-            0xA => {
-                // set index to nnn
-                format!("set index to 0x{:03X}", nnn)
-            }
-            0xB => {
-                // TODO: configure this to be optional: BXNN -> jump to XNN + VX
-                // jump to nnn + V0
-                format!("jump to 0x{:03X} + V0", nnn)
-            }
-            0xC => {
-                // set Vx to random number & nn
-                format!("set V{:X} to random number & 0x{:02x}", x, nn)
+        }
+        lines
+    }
+    /// The instruction in the syntax of the disassembler, or `unknown` if the platform does not have it.
+    pub fn disassemble_opcode(&self, opcode: u16) -> String {
+        match Instruction::decode(opcode) {
+            Ok(instruction) if instruction.available_on(self.platform) => {
+                instruction.format(self.syntax, self.platform, &self.quirks)
             }
-            0xD => {
-                // display
-                format!("display at V{:X}, V{:X}, 0x{:X}", x, y, n)
+            _ => "unknown".to_owned(),
+        }
+    }
+    /// Defines a label in the syntax of the disassembler.
+    fn label_line(&self, label: &str) -> String {
+        match self.syntax {
+            Syntax::Octo => format!(": {}", label),
+            _ => format!("{}:", label),
+        }
+    }
+    /// Starts a comment in the syntax of the disassembler.
+    fn comment(&self) -> &'static str {
+        match self.syntax {
+            Syntax::Octo => "#",
+            _ => ";",
+        }
+    }
+    /// The instruction at `address` with its address and opcode.
+    fn code_line(
+        &self,
+        address: usize,
+        instruction: &Instruction,
+        labels: &BTreeMap<usize, String>,
+    ) -> String {
+        let (opcode, text) = match instruction {
+            Instruction::LongIndex => {
+                let target = self.word(address + 2);
+                let operand = match labels.get(&(target as usize)) {
+                    Some(label) => label.clone(),
+                    None => format!("0x{:04X}", target),
+                };
+                (
+                    format!("{:04X} {:04X}", instruction.encode(), target),
+                    self.long_index(&operand),
+                )
             }
-            0xF => {
-                match nn {
-                    0x07 => {
-                        // set Vx to delay timer
-                        format!("set V{:X} to delay timer", x)
-                    }
-                    0x15 => {
-                        // set delay timer to Vx
-                        format!("set delay timer to V{:X}", x)
-                    }
-                    0x18 => {
-                        // set sound timer to Vx
-                        format!("set sound timer to V{:X}", x)
-                    }
-                    0x1E => {
-                        // add Vx to index
-                        // TODO: VF is set to 1 when there is a range overflow (I + Vx > 0xFFF)
-                        format!("add V{:X} to index", x)
-                    }
-                    0x0A => {
-                        format!("wait for key press and store in V{:X}", x)
-                    }
-                    0x29 => {
-                        // set index to location of sprite for digit Vx
-                        format!("set index to location of sprite for digit V{:X}", x)
-                    }
-                    0x33 => {
-                        // store BCD representation of Vx in memory locations I, I+1, I+2
-                        format!(
-                            "store BCD representation of V{:X} in memory locations I, I+1, I+2",
-                            x
-                        )
-                    }
-                    0x55 => {
-                        // store V0 to Vx in memory starting at I
-                        format!("store V0 to V{:X} in memory starting at I", x)
-                    }
-                    0x65 => {
-                        // fill V0 to Vx with memory starting at I
-                        format!("fill V0 to V{:X} with memory starting at I", x)
-                    }
-                    _ => "unknown".to_owned(),
-                }
+            _ => (
+                format!("{:04X}", self.word(address)),
+                self.text(instruction, labels),
+            ),
+        };
+        match self.syntax {
+            Syntax::Octo => format!("{:<24} # {:04X}: {}", text, address, opcode),
+            _ => format!("{:04X}: {:<9} {}", address, opcode, text),
+        }
+    }
+    /// Like [`Disassembler::disassemble_opcode`], with `next`, the word after the opcode, as the address of
+    /// F000 NNNN.
+    pub fn disassemble_with_operand(&self, opcode: u16, next: u16) -> String {
+        match Instruction::decode(opcode) {
+            Ok(Instruction::LongIndex) if Instruction::LongIndex.available_on(self.platform) => {
+                self.long_index(&format!("0x{:04X}", next))
             }
-            _ => "unknown".to_owned(),
+            _ => self.disassemble_opcode(opcode),
         }
     }
+    /// F000 NNNN with its address or label.
+    fn long_index(&self, operand: &str) -> String {
+        match self.syntax {
+            Syntax::Description => format!("set index to {}", operand),
+            Syntax::Classic => format!("{} {}", Instruction::LongIndex, operand),
+            Syntax::Octo => format!("{} {}", Instruction::LongIndex.octo(), operand),
+        }
+    }
+    /// The instruction with its address operand replaced by a label.
+    fn text(&self, instruction: &Instruction, labels: &BTreeMap<usize, String>) -> String {
+        let text = instruction.format(self.syntax, self.platform, &self.quirks);
+        match instruction
+            .address()
+            .and_then(|target| Some((target, labels.get(&(target as usize))?)))
+        {
+            // every syntax writes the address operand the same way
+            Some((target, label)) => text.replacen(&format!("0x{:03X}", target), label, 1),
+            None => text,
+        }
+    }
+    /// Decodes the instruction at `address`, if it is one of the platform.
+    fn instruction_at(&self, address: usize) -> Option<Instruction> {
+        if address < START || address + 1 >= self.memory.len() {
+            return None;
+        }
+        let instruction = Instruction::decode(self.word(address)).ok()?;
+        let fits = address + instruction.size() <= self.memory.len();
+        (instruction.available_on(self.platform) && fits).then_some(instruction)
+    }
+    /// Size of the instruction a skip at `address` jumps over, like the interpreter sees it.
+    fn size_at(&self, address: usize) -> usize {
+        if self.platform.has_xochip()
+            && address + 1 < self.memory.len()
+            && self.word(address) == 0xF000
+        {
+            4
+        } else {
+            2
+        }
+    }
+    /// Big endian word at `address`, 0 past the end of memory.
+    fn word(&self, address: usize) -> u16 {
+        let byte = |address: usize| self.memory.get(address).copied().unwrap_or(0) as u16;
+        byte(address) << 8 | byte(address + 1)
+    }
+    /// End of the program: after its last instruction or the last byte that is not 0, whichever comes later.
+    fn end(&self, code: &BTreeMap<usize, Instruction>) -> usize {
+        let data = self.memory[START.min(self.memory.len())..]
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(START, |last| START + last + 1);
+        let code = code
+            .iter()
+            .map(|(address, instruction)| address + instruction.size())
+            .max()
+            .unwrap_or(START);
+        data.max(code)
+    }
+}
+
+/// Instructions reached from the start of the program and names for the addresses they refer to.
+pub struct Analysis {
+    pub code: BTreeMap<usize, Instruction>,
+    pub labels: BTreeMap<usize, String>,
+    /// Address after the last byte of the program.
+    pub end: usize,
 }
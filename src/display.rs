@@ -0,0 +1,137 @@
+//! Colour themes and the layout of the framebuffer inside the window.
+
+use crate::consts::{PALETTE, WINDOW_SCALE};
+
+/// Named palettes for the four bitplane combinations: background, foreground, plane 2 and both planes.
+pub const THEMES: [(&str, [[u8; 3]; 4]); 6] = [
+    ("classic", PALETTE),
+    (
+        "inverted",
+        [[255, 255, 255], [0, 0, 0], [85, 85, 85], [170, 170, 170]],
+    ),
+    (
+        "amber",
+        [[26, 16, 0], [255, 176, 0], [170, 100, 0], [255, 220, 140]],
+    ),
+    (
+        "green",
+        [[8, 24, 8], [51, 255, 102], [20, 140, 60], [170, 255, 190]],
+    ),
+    (
+        "gameboy",
+        [[155, 188, 15], [15, 56, 15], [48, 98, 48], [139, 172, 15]],
+    ),
+    (
+        "octo",
+        [[153, 102, 0], [255, 204, 0], [255, 102, 0], [102, 34, 0]],
+    ),
+];
+
+pub fn theme(name: &str) -> Option<[[u8; 3]; 4]> {
+    THEMES
+        .iter()
+        .find(|(theme, _)| theme.eq_ignore_ascii_case(name))
+        .map(|(_, palette)| *palette)
+}
+
+/// Parses a colour written as `#RRGGBB` or `RRGGBB`.
+pub fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// How the framebuffer is fitted into a window of a different size.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Scaling {
+    /// Whole multiples of the framebuffer, sharp but with wider borders.
+    #[default]
+    Integer,
+    /// As large as the window allows, pixels may differ in size by one.
+    Fit,
+}
+
+impl Scaling {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "integer" => Some(Scaling::Integer),
+            "fit" => Some(Scaling::Fit),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DisplaySettings {
+    /// Size of a low resolution pixel in the initial window, may be fractional.
+    pub scale: f32,
+    pub scaling: Scaling,
+    pub fullscreen: bool,
+    /// Lets the user resize the window, the picture is letterboxed to keep its aspect ratio.
+    pub resizable: bool,
+    /// Draws thin lines between the pixels.
+    pub grid: bool,
+}
+
+impl DisplaySettings {
+    /// Size of the window at startup.
+    pub fn window_size(&self) -> (u32, u32) {
+        let width = (64.0 * self.scale).round().max(64.0) as u32;
+        (width, width / 2)
+    }
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            scale: WINDOW_SCALE,
+            scaling: Scaling::default(),
+            fullscreen: false,
+            resizable: true,
+            grid: false,
+        }
+    }
+}
+
+/// Area of a `window` sized output the framebuffer is drawn to, as x, y, width and height.
+///
+/// The rest of the window stays black, like the bars of a letterboxed film.
+pub fn viewport(
+    window: (u32, u32),
+    framebuffer: (u32, u32),
+    scaling: Scaling,
+) -> (i32, i32, u32, u32) {
+    let (window_width, window_height) = window;
+    let (columns, rows) = framebuffer;
+    let (width, height) = match scaling {
+        Scaling::Integer => {
+            let pixel = (window_width / columns).min(window_height / rows).max(1);
+            (columns * pixel, rows * pixel)
+        }
+        Scaling::Fit => {
+            // largest area with the aspect ratio of the framebuffer
+            let width = window_width
+                .min(window_height * columns / rows)
+                .max(columns);
+            (width, width * rows / columns)
+        }
+    };
+    (
+        (window_width as i32 - width as i32) / 2,
+        (window_height as i32 - height as i32) / 2,
+        width,
+        height,
+    )
+}
+
+/// Colour of the grid lines, the background shifted a little towards the foreground.
+pub fn grid_color(palette: &[[u8; 3]; 4]) -> [u8; 3] {
+    let [background, foreground, ..] = palette;
+    [0, 1, 2].map(|i| {
+        let (b, f) = (background[i] as i32, foreground[i] as i32);
+        (b + (f - b) / 5) as u8
+    })
+}
@@ -1,20 +1,140 @@
 use oorandom::Rand32;
-use sdl2::{event::Event, keyboard::Keycode, render::Canvas, video::Window, Sdl};
 
-use std::{thread, time::Duration};
+use std::{
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use crate::consts::{FONT_BASE_ADDRESS, KEYS, SLEEP_MICROS};
+use crate::{
+    audio::Sound,
+    config::Config,
+    consts::{BIG_FONT_BASE_ADDRESS, FAST_FORWARD, FONT_BASE_ADDRESS, SLOW_MOTION, STACK_SIZE},
+    error::Chip8Error,
+    frontend::{Frontend, Hotkey},
+    instruction::Instruction,
+    movie::Movie,
+    platform::Platform,
+    quirks::{IndexIncrement, Quirks},
+    rewind::Rewind,
+    savestate, screenshot,
+    trace::Tracer,
+    video::{self, VideoRecorder},
+};
 
+/// Frame buffer, 64x32 in low resolution and 128x64 in high resolution mode.
+///
+/// Every pixel holds one bit per bitplane, plain CHIP-8 and SUPER-CHIP only ever use the first plane.
 #[derive(Clone)]
 pub struct Graphics {
-    buffer: [u8; 64 * 32],
+    pub(crate) buffer: [u8; 128 * 64],
+    pub(crate) hires: bool,
+    /// Bitplanes affected by drawing, clearing and scrolling, selected by the XO-CHIP FN01 instruction.
+    pub planes: u8,
+    /// Set when the pixels change, frontends skip frames that are not dirty.
+    pub(crate) dirty: bool,
 }
 impl Graphics {
     pub fn new() -> Self {
         Graphics {
-            buffer: [0; 64 * 32],
+            buffer: [0; 128 * 64],
+            hires: false,
+            planes: 1,
+            dirty: true,
         }
     }
+    pub fn width(&self) -> usize {
+        if self.hires {
+            128
+        } else {
+            64
+        }
+    }
+    pub fn height(&self) -> usize {
+        if self.hires {
+            64
+        } else {
+            32
+        }
+    }
+    pub fn hires(&self) -> bool {
+        self.hires
+    }
+    /// Switches the resolution, which also clears all planes.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.buffer = [0; 128 * 64];
+        self.dirty = true;
+    }
+    /// True if the pixels changed since the frame was last presented.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+    /// Called after the frame was presented.
+    pub fn mark_clean(&mut self) {
+        self.dirty = false;
+    }
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.buffer[x + y * self.width()] != 0
+    }
+    /// Returns the bitplanes set at the pixel, used as the colour index.
+    pub fn color(&self, x: usize, y: usize) -> u8 {
+        self.buffer[x + y * self.width()]
+    }
+    /// SHA-1 of the resolution and the visible pixels in hex, for comparing frames.
+    pub fn digest(&self) -> String {
+        let mut sha1 = sha1_smol::Sha1::new();
+        sha1.update(&[self.hires as u8]);
+        sha1.update(&self.buffer[..self.width() * self.height()]);
+        sha1.digest().to_string()
+    }
+    /// Clears the selected planes.
+    pub fn clear(&mut self) {
+        for pixel in self.buffer.iter_mut() {
+            *pixel &= !self.planes;
+        }
+        self.dirty = true;
+    }
+    /// Flips the pixel on the given plane and returns true if it was set before.
+    fn toggle(&mut self, x: usize, y: usize, plane: u8) -> bool {
+        let idx = x + y * self.width();
+        let old = self.buffer[idx] & plane != 0;
+        self.buffer[idx] ^= plane;
+        self.dirty = true;
+        old
+    }
+    /// Moves the selected planes by dx, dy pixels, filling the uncovered area with 0.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let old = self.buffer;
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x - dx, y - dy);
+                let moved = if sx >= 0 && sx < width && sy >= 0 && sy < height {
+                    old[(sx + sy * width) as usize] & self.planes
+                } else {
+                    0
+                };
+                let idx = (x + y * width) as usize;
+                self.buffer[idx] = (self.buffer[idx] & !self.planes) | moved;
+            }
+        }
+        self.dirty = true;
+    }
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll(0, n as isize);
+    }
+    pub fn scroll_up(&mut self, n: usize) {
+        self.scroll(0, -(n as isize));
+    }
+    pub fn scroll_right(&mut self, n: usize) {
+        self.scroll(n as isize, 0);
+    }
+    pub fn scroll_left(&mut self, n: usize) {
+        self.scroll(-(n as isize), 0);
+    }
 }
 impl Default for Graphics {
     fn default() -> Self {
@@ -22,82 +142,225 @@ impl Default for Graphics {
     }
 }
 
+#[derive(Clone, Copy, Debug, Default)]
 pub struct KeyState {
-    pub key: Option<u8>,
+    pub keys: [bool; 16],
 }
-
-pub fn find_sdl_gl_driver() -> Option<u32> {
-    for (index, item) in sdl2::render::drivers().enumerate() {
-        if item.name == "opengl" {
-            return Some(index as u32);
+impl KeyState {
+    pub fn new() -> Self {
+        KeyState { keys: [false; 16] }
+    }
+    pub fn press(&mut self, key: u8) {
+        self.keys[key as usize & 0xF] = true;
+    }
+    pub fn release(&mut self, key: u8) {
+        self.keys[key as usize & 0xF] = false;
+    }
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys[key as usize & 0xF]
+    }
+    /// Held keys as a bit mask, bit N is key N.
+    pub fn mask(&self) -> u16 {
+        (0..16)
+            .filter(|&k| self.keys[k])
+            .fold(0, |mask, k| mask | 1 << k)
+    }
+    pub fn from_mask(mask: u16) -> Self {
+        let mut keys = [false; 16];
+        for (k, key) in keys.iter_mut().enumerate() {
+            *key = mask & 1 << k != 0;
         }
+        KeyState { keys }
+    }
+    /// Returns the lowest key that is currently held down.
+    pub fn first_pressed(&self) -> Option<u8> {
+        self.keys.iter().position(|&k| k).map(|k| k as u8)
     }
-    None
 }
-pub fn emulate(program: &[u8]) {
-    let sdl_context = sdl2::init().expect("sdl2 should initialize");
 
-    let timer = sdl_context
-        .timer()
-        .expect("sdl2 context should have a timer");
-    let mut display = Display::new(&sdl_context);
-    let mut event_pump = sdl_context
-        .event_pump()
-        .expect("sdl2 context should have an event pump");
+/// Draws a seed from the OS, falling back to the clock if that fails.
+pub fn random_seed() -> u64 {
+    let mut bytes = [0; 8];
+    match getrandom::getrandom(&mut bytes) {
+        Ok(()) => u64::from_le_bytes(bytes),
+        Err(_) => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default(),
+    }
+}
 
-    let mut emulator = Emulator::new();
+pub fn emulate(program: &[u8], config: &Config, frontend: &mut dyn Frontend) {
+    let mut emulator = Emulator::with_config(config);
     emulator.load(program);
-    display.canvas.present();
+    emulator.tracer = Tracer::from_config(config);
 
-    let mut before;
-    let mut next: u64 = 0;
+    let frame = Duration::from_secs(1) / 60;
+    let mut next = Instant::now();
+    // frames to run are accumulated so that fractional speeds work out over time
+    let mut frames = 0.0;
+    let mut fast_forward = false;
+    let mut slow_motion = false;
+    let mut slot = 0;
+    let mut rewind = Rewind::new(config.rewind_seconds);
+    let mut rewinding = false;
+    rewind.push(&emulator);
+    let mut recording = config
+        .record
+        .as_ref()
+        .map(|_| Movie::new(&emulator, program));
+    let mut video = config
+        .record_video
+        .as_ref()
+        .and_then(|path| VideoRecorder::from_config(path, config));
+    // frames run since the start, the position in the movie being recorded or played
+    let mut frame_count = 0;
     'run: loop {
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => break 'run,
-                Event::KeyDown {
-                    keycode: Some(keycode),
-                    ..
-                } => {
-                    let key = KEYS.iter().position(|&x| x == keycode);
-                    if let Some(k) = key {
-                        emulator.key_buffer.key = Some(k as u8);
+        for hotkey in frontend.poll(&mut emulator.key_buffer) {
+            match hotkey {
+                Hotkey::Quit => break 'run,
+                Hotkey::FastForward(held) => fast_forward = held,
+                Hotkey::SlowMotion(held) => slow_motion = held,
+                Hotkey::Rewind(held) => rewinding = held,
+                Hotkey::NextSlot => {
+                    slot = (slot + 1) % savestate::SLOTS;
+                    println!("Save state slot {}", slot);
+                }
+                Hotkey::Screenshot => {
+                    let Some(rom) = &config.rom_path else {
+                        println!("Screenshots need the path of the ROM");
+                        continue;
+                    };
+                    let path = screenshot::next_path(rom);
+                    match screenshot::save(
+                        &emulator.graphics,
+                        &path,
+                        &config.palette,
+                        config.screenshot_scale,
+                    ) {
+                        Ok(()) => println!("Saved screenshot to {}", path.display()),
+                        Err(err) => println!("Could not save screenshot: {}", err),
                     }
                 }
-                Event::KeyUp {
-                    keycode: Some(keycode),
-                    ..
-                } => {
-                    let key = KEYS.iter().position(|&x| x == keycode);
-                    if let Some(key) = key {
-                        if let Some(k) = emulator.key_buffer.key {
-                            if k == key as u8 {
-                                emulator.key_buffer.key = None;
-                            }
+                Hotkey::ToggleVideo => {
+                    if let Some(recorder) = video.take() {
+                        recorder.stop();
+                        continue;
+                    }
+                    let Some(rom) = &config.rom_path else {
+                        println!("Videos need the path of the ROM");
+                        continue;
+                    };
+                    video = VideoRecorder::from_config(&video::next_path(rom), config);
+                    if let Some(recorder) = &video {
+                        println!("Recording video to {}", recorder.path.display());
+                    }
+                }
+                Hotkey::LoadState if recording.is_some() || config.play.is_some() => {
+                    println!("Save states cannot be loaded while a movie is recorded or played");
+                }
+                Hotkey::SaveState | Hotkey::LoadState => {
+                    let Some(rom) = &config.rom_path else {
+                        println!("Save states need the path of the ROM");
+                        continue;
+                    };
+                    let path = savestate::slot_path(rom, slot);
+                    let result = if hotkey == Hotkey::SaveState {
+                        savestate::save_file(&emulator, &path)
+                    } else {
+                        savestate::load_file(&mut emulator, &path)
+                    };
+                    match result {
+                        Ok(()) if hotkey == Hotkey::SaveState => {
+                            println!("Saved state to slot {}", slot)
                         }
+                        Ok(()) => println!("Loaded state from slot {}", slot),
+                        Err(err) => println!("{}", err),
                     }
                 }
-                _ => {}
             }
         }
-        before = timer.ticks64();
-        if before >= next {
-            if emulator.delay_timer > 0 {
-                println!("Delay timer: {}", emulator.delay_timer);
-                emulator.delay_timer -= 1;
+        frames += if fast_forward {
+            FAST_FORWARD
+        } else if slow_motion {
+            SLOW_MOTION
+        } else {
+            1.0
+        };
+        let mut running = true;
+        while running && frames >= 1.0 {
+            frames -= 1.0;
+            if rewinding {
+                // keep the keys the user is holding right now
+                let keys = emulator.key_buffer;
+                if rewind.step_back(&mut emulator) {
+                    frame_count -= 1;
+                    if let Some(movie) = recording.as_mut() {
+                        movie.frames.truncate(frame_count);
+                    }
+                } else {
+                    frames = 0.0;
+                }
+                emulator.key_buffer = keys;
+                continue;
+            }
+            if let Some(movie) = &config.play {
+                match movie.frames.get(frame_count) {
+                    Some(&keys) => emulator.key_buffer = KeyState::from_mask(keys),
+                    None if frame_count == movie.frames.len() => {
+                        println!("Movie finished after {} frames", frame_count);
+                        emulator.key_buffer = KeyState::new();
+                    }
+                    None => {}
+                }
+            }
+            if let Some(movie) = recording.as_mut() {
+                movie.frames.push(emulator.key_buffer.mask());
             }
-            if emulator.sound_timer > 0 {
-                emulator.sound_timer -= 1;
+            frame_count += 1;
+            if let Err(err) = emulator.run_frame() {
+                println!("{}", err);
+                running = false;
+            }
+            if emulator.exited {
+                running = false;
+            }
+            rewind.push(&emulator);
+        }
+        frontend.present(&emulator.graphics);
+        emulator.graphics.mark_clean();
+        if let Some(recorder) = video.as_mut() {
+            if let Err(err) = recorder.push(&emulator.graphics) {
+                println!("Stopped recording video: {}", err);
+                video = None;
             }
-            next = before + 1000 / 60;
         }
-        const STEPS: usize = 2;
-        for _ in 0..STEPS {
-            emulator.run(Some(&mut display));
+        frontend.set_playing(emulator.sound().filter(|_| !rewinding));
+        if !running {
+            break 'run;
+        }
+
+        // sleep once per frame, without trying to catch up after a stall
+        next += frame;
+        let now = Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        } else {
+            next = now;
+        }
+    }
+
+    if let Some(recorder) = video {
+        recorder.stop();
+    }
+    if let (Some(path), Some(movie)) = (&config.record, &recording) {
+        match movie.save_file(path) {
+            Ok(()) => println!(
+                "Recorded {} frames to {}",
+                movie.frames.len(),
+                path.display()
+            ),
+            Err(err) => println!("{}", err),
         }
     }
 }
@@ -107,65 +370,45 @@ pub struct Register {
     pub v: u8,
 }
 
-pub struct Display {
-    pub canvas: Canvas<Window>,
-}
-
-impl Display {
-    pub fn new(context: &Sdl) -> Self {
-        let video_subsystem = context.video().unwrap();
-        let window = video_subsystem
-            .window("CHIP 8", 640, 320)
-            .opengl()
-            .position_centered()
-            .build()
-            .unwrap();
-        let canvas = window
-            .into_canvas()
-            .index(find_sdl_gl_driver().unwrap())
-            .build()
-            .unwrap();
-        Display { canvas }
-    }
-    fn draw(&mut self, graphics: &Graphics) {
-        for y in 0..32 {
-            for x in 0..64 {
-                let idx = x + y * 64;
-                let color = if graphics.buffer[idx] == 0 { 0 } else { 255 };
-                if color == 0 {
-                    self.canvas
-                        .set_draw_color(sdl2::pixels::Color::RGB(0, 0, 0));
-                } else {
-                    self.canvas
-                        .set_draw_color(sdl2::pixels::Color::RGB(255, 255, 255));
-                }
-
-                self.canvas
-                    .fill_rect(sdl2::rect::Rect::new(x as i32 * 10, y as i32 * 10, 10, 10))
-                    .unwrap();
-            }
-        }
-        self.canvas.present();
-    }
-}
-
 pub struct Emulator {
-    pub memory: [u8; 4096],
+    pub memory: Vec<u8>,
     pub graphics: Graphics,
     pub key_buffer: KeyState,
+    /// Key that was pressed while FX0A is waiting; the instruction completes once it is released.
+    pub waiting_key: Option<u8>,
     pub pc: usize,
     pub stack: Vec<u16>,
     pub registers: [Register; 16],
     pub index: u16,
     pub instruction: u16,
-    pub instr: u16,
-    pub x: u16,
-    pub y: u16,
-    pub n: u16,
-    pub nn: u16,
-    pub nnn: u16,
+    /// Address the current instruction was fetched from.
+    pub instruction_address: usize,
     pub delay_timer: u16,
     pub sound_timer: u16,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    /// SUPER-CHIP RPL user flags saved and restored by FX75/FX85.
+    pub rpl: [u8; 16],
+    /// XO-CHIP 1-bit audio pattern loaded by F002, played instead of the tone while the sound timer is running.
+    pub audio_pattern: Option<[u8; 16]>,
+    /// XO-CHIP playback pitch set by FX3A.
+    pub pitch: u8,
+    /// Set by 00FD, the frontend should stop running the program.
+    pub exited: bool,
+    /// Set on every timer tick, consumed by DXYN when `quirks.display_wait` is on.
+    pub vblank: bool,
+    /// Number of instructions executed per 60 Hz frame by `step` and `run_frame`.
+    pub instructions_per_frame: u32,
+    /// Instructions executed since the last timer tick.
+    pub frame_cycle: u32,
+    /// Instructions executed since the program was loaded.
+    pub cycles: u64,
+    /// Logs every instruction that executed, with the state before it ran.
+    pub tracer: Option<Tracer>,
+    /// Seed the random number generator was created with.
+    pub seed: u64,
+    /// Random number generator used by CXNN, `rng.state()` captures its position.
+    pub rng: Rand32,
 }
 impl Default for Emulator {
     fn default() -> Self {
@@ -173,299 +416,527 @@ impl Default for Emulator {
     }
 }
 impl Emulator {
+    /// A CHIP-8 with the default quirks, which do not wait for the vertical blank. Pass [`Quirks::vip`] to
+    /// [`Emulator::with_quirks`] for the wait, then drive the emulator with [`Emulator::step`] or
+    /// [`Emulator::run_frame`] so timer ticks end it.
     pub fn new() -> Self {
+        Self::with_quirks(Quirks::default())
+    }
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        Self::with_platform(Platform::Chip8, quirks)
+    }
+    pub fn with_config(config: &Config) -> Self {
+        let mut emulator = Self::with_platform(config.platform, config.quirks);
+        emulator.instructions_per_frame = config.instructions_per_frame;
+        if let Some(seed) = config.seed {
+            emulator.reseed(seed);
+        }
+        emulator
+    }
+    pub fn with_platform(platform: Platform, quirks: Quirks) -> Self {
+        let seed = random_seed();
         Emulator {
-            memory: [0; 4096],
+            memory: vec![0; platform.memory_size()],
             graphics: Graphics::new(),
-            key_buffer: KeyState { key: None },
+            key_buffer: KeyState::new(),
+            waiting_key: None,
             pc: 0x200,
             stack: Vec::new(),
             registers: [Register { v: 0 }; 16],
             index: 0,
             instruction: 0,
-            instr: 0,
-            x: 0,
-            y: 0,
-            n: 0,
-            nn: 0,
-            nnn: 0,
+            instruction_address: 0x200,
             delay_timer: 0,
             sound_timer: 0,
+            platform,
+            quirks,
+            rpl: [0; 16],
+            audio_pattern: None,
+            pitch: 64,
+            exited: false,
+            vblank: false,
+            instructions_per_frame: platform.default_instructions_per_frame(),
+            frame_cycle: 0,
+            cycles: 0,
+            tracer: None,
+            seed,
+            rng: Rand32::new(seed),
         }
     }
+    /// Restarts the random number generator from `seed`.
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Rand32::new(seed);
+    }
     pub fn load(&mut self, program: &[u8]) {
-        self.memory = program.try_into().expect("Program should be 4096 bytes");
+        assert!(
+            program.len() == self.memory.len(),
+            "Program should be {} bytes",
+            self.memory.len()
+        );
+        self.memory.copy_from_slice(program);
     }
-    fn fetch(&mut self) {
-        if self.pc >= 4096 {
-            panic!("Program counter out of bounds");
+    fn fetch(&mut self) -> Result<(), Chip8Error> {
+        if self.pc + 1 >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfRange { pc: self.pc });
         }
+        self.instruction_address = self.pc;
         let first_byte = self.memory[self.pc];
         let second_byte = self.memory[self.pc + 1];
         self.pc += 2;
         self.instruction = ((first_byte as u16) << 8) | second_byte as u16;
+        Ok(())
     }
-    fn decode(&mut self) {
-        self.instr = (self.instruction & 0xF000) >> 12;
-        self.x = (self.instruction & 0x0F00) >> 8;
-        self.y = (self.instruction & 0x00F0) >> 4;
-        self.n = self.instruction & 0x000F;
-        self.nn = self.instruction & 0x00FF;
-        self.nnn = self.instruction & 0x0FFF;
-        // println!("instr: {:x}, x: {:x}, y: {:x}, n: {:x}, nn: {:x}, nnn: {:x}", self.instr, self.x, self.y, self.n, self.nn, self.nnn);
-    }
-    fn execute(&mut self, display: Option<&mut Display>) {
-        match self.instr {
-            0x0 => {
-                match self.nnn {
-                    0x0E0 => {
-                        // clear screen
-                        self.clear_screen();
-                        if let Some(display) = display {
-                            display.draw(&self.graphics);
-                        }
-                    }
-                    0x0EE => {
-                        // return from subroutine
-                        self.pc = self.stack.pop().unwrap() as usize;
-                    }
-                    _ => {
-                        panic!(
-                            "Unknown instruction: 0x{:04x} at 0x{:04x}",
-                            self.instruction,
-                            self.pc - 2
-                        );
+    fn decode(&self) -> Result<Instruction, Chip8Error> {
+        Instruction::decode(self.instruction)
+            .ok()
+            .filter(|instruction| instruction.available_on(self.platform))
+            .ok_or_else(|| self.unknown_opcode())
+    }
+    /// Executes `instruction`, `Ok(false)` if it waits for the vertical blank or a key and runs again.
+    fn execute(&mut self, instruction: Instruction) -> Result<bool, Chip8Error> {
+        match instruction {
+            Instruction::ClearScreen => {
+                self.clear_screen();
+            }
+            Instruction::Return => {
+                self.pc = match self.stack.pop() {
+                    Some(address) => address as usize,
+                    None => {
+                        return Err(Chip8Error::StackUnderflow {
+                            address: self.instruction_address,
+                            opcode: self.instruction,
+                        })
                     }
-                }
+                };
+            }
+            Instruction::ScrollUp(n) => {
+                self.graphics.scroll_up(n as usize);
+            }
+            Instruction::ScrollDown(n) => {
+                self.graphics.scroll_down(n as usize);
+            }
+            Instruction::ScrollRight => {
+                self.graphics.scroll_right(4);
             }
-            0x1 => {
-                // jump
-                self.pc = self.nnn as usize;
+            Instruction::ScrollLeft => {
+                self.graphics.scroll_left(4);
             }
-            0x2 => {
-                // call subroutine
+            Instruction::Exit => {
+                // stay on the instruction so the program does not run on
+                self.pc -= 2;
+                self.exited = true;
+            }
+            Instruction::LowRes => {
+                self.graphics.set_hires(false);
+            }
+            Instruction::HighRes => {
+                self.graphics.set_hires(true);
+            }
+            Instruction::Jump(nnn) => {
+                self.pc = nnn as usize;
+            }
+            Instruction::Call(nnn) => {
+                if self.stack.len() >= STACK_SIZE {
+                    return Err(Chip8Error::StackOverflow {
+                        address: self.instruction_address,
+                        opcode: self.instruction,
+                    });
+                }
                 self.stack.push(self.pc as u16);
-                self.pc = self.nnn as usize;
+                self.pc = nnn as usize;
             }
-            0x3 => {
-                // skip next instruction if Vx == nn
-                if self.registers[self.x as usize].v == self.nn as u8 {
-                    self.pc += 2;
+            Instruction::SkipEqual { x, nn } => {
+                if self.registers[x as usize].v == nn {
+                    self.skip_next();
                 }
             }
-            0x4 => {
-                // skip next instruction if Vx != nn
-                if self.registers[self.x as usize].v != self.nn as u8 {
-                    self.pc += 2;
+            Instruction::SkipNotEqual { x, nn } => {
+                if self.registers[x as usize].v != nn {
+                    self.skip_next();
                 }
             }
-            0x5 => {
-                // skip next instruction if Vx == Vy
-                if self.registers[self.x as usize].v == self.registers[self.y as usize].v {
-                    self.pc += 2;
+            Instruction::SkipEqualRegisters { x, y } => {
+                if self.registers[x as usize].v == self.registers[y as usize].v {
+                    self.skip_next();
                 }
             }
-            0x6 => {
-                // set Vx to nn
-                self.registers[self.x as usize].v = self.nn as u8;
-            }
-            0x7 => {
-                // add nn to Vx
-                self.registers[self.x as usize].v = self.registers[self.x as usize]
-                    .v
-                    .wrapping_add(self.nn as u8);
-            }
-            0x8 => {
-                match (self.instruction & 0x000F) as u8 {
-                    0x0 => {
-                        // set Vx to Vy
-                        self.registers[self.x as usize].v = self.registers[self.y as usize].v;
-                    }
-                    0x1 => {
-                        // set Vx to Vx | Vy
-                        self.registers[self.x as usize].v |= self.registers[self.y as usize].v;
-                    }
-                    0x2 => {
-                        // set Vx to Vx & Vy
-                        self.registers[self.x as usize].v &= self.registers[self.y as usize].v;
-                    }
-                    0x3 => {
-                        // set Vx to Vx ^ Vy
-                        self.registers[self.x as usize].v ^= self.registers[self.y as usize].v;
-                    }
-                    0x4 => {
-                        // add Vx to Vy
-                        let result = self.registers[self.x as usize].v as u16
-                            + self.registers[self.y as usize].v as u16;
-                        self.registers[self.x as usize].v = result as u8;
-                        self.registers[0xF].v = if result > 0xFF { 1 } else { 0 };
-                    }
-                    0x5 => {
-                        // subtract Vy from Vx
-                        let x = self.registers[self.x as usize].v;
-                        let y = self.registers[self.y as usize].v;
-                        self.registers[self.x as usize].v = x.wrapping_sub(y);
-                        self.registers[0xF].v = if x > y { 1 } else { 0 };
-                    }
-                    0x6 => {
-                        // TODO: configure this to be optional
-                        self.registers[self.x as usize].v = self.registers[self.y as usize].v;
-                        let flag = self.registers[self.x as usize].v & 0x1;
-                        self.registers[self.x as usize].v >>= 1;
-                        self.registers[0xF].v = flag;
-                    }
-                    0x7 => {
-                        // subtract Vx from Vy
-                        let x = self.registers[self.x as usize].v;
-                        let y = self.registers[self.y as usize].v;
-                        self.registers[self.x as usize].v = y.wrapping_sub(x);
-                        self.registers[0xF].v = if y > x { 1 } else { 0 };
-                    }
-                    0xE => {
-                        //TODO: configure this to be optional
-                        self.registers[self.x as usize].v = self.registers[self.y as usize].v;
-                        let flag = (self.registers[self.x as usize].v & 0x80) >> 7;
-                        self.registers[self.x as usize].v <<= 1;
-                        self.registers[0xF].v = flag;
-                    }
-                    _ => {
-                        panic!(
-                            "Unknown instruction: 0x{:04x} at 0x{:04x}",
-                            self.instruction,
-                            self.pc - 2
-                        );
-                    }
+            Instruction::StoreRange { x, y } => {
+                for (offset, reg) in register_range(x, y).enumerate() {
+                    self.write(self.index as usize + offset, self.registers[reg].v)?;
                 }
             }
-            0x9 => {
-                // skip next instruction if Vx != Vy
-                if self.registers[self.x as usize].v != self.registers[self.y as usize].v {
-                    self.pc += 2;
+            Instruction::LoadRange { x, y } => {
+                for (offset, reg) in register_range(x, y).enumerate() {
+                    self.registers[reg].v = self.read(self.index as usize + offset)?;
                 }
             }
-            0xA => {
-                // set index to nnn
-                self.index = self.nnn;
-            }
-            0xB => {
-                // TODO: configure this to be optional: BXNN -> jump to XNN + VX
-                // jump to nnn + V0
-                self.pc = self.nnn as usize + self.registers[0].v as usize;
-            }
-            0xC => {
-                // set Vx to random number & nn
-                let mut rng = Rand32::new(0);
-                self.registers[self.x as usize].v = rng.rand_u32() as u8 & self.nn as u8;
-            }
-            0xD => {
-                // display
-                let x = self.registers[self.x as usize].v as usize % 64;
-                let y = self.registers[self.y as usize].v as usize % 32;
-                self.registers[0xF].v = 0;
-
-                let mut sprite = [0u8; 15];
-                for s in self.index..(self.index + self.n) {
-                    sprite[(s - self.index) as usize] = self.memory[s as usize];
+            Instruction::Set { x, nn } => {
+                self.registers[x as usize].v = nn;
+            }
+            Instruction::Add { x, nn } => {
+                self.registers[x as usize].v = self.registers[x as usize].v.wrapping_add(nn);
+            }
+            Instruction::Copy { x, y } => {
+                self.registers[x as usize].v = self.registers[y as usize].v;
+            }
+            Instruction::Or { x, y } => {
+                self.registers[x as usize].v |= self.registers[y as usize].v;
+                if self.quirks.vf_reset {
+                    self.registers[0xF].v = 0;
                 }
-
-                for (row, _) in sprite.iter().enumerate().take(self.n as usize) {
-                    let rev = [7, 6, 5, 4, 3, 2, 1, 0];
-                    for (col, _) in rev.iter().enumerate() {
-                        let xx = rev[col] + x;
-                        let yy = row + y;
-                        let old_pixel = self.graphics.buffer[xx + yy * 64] != 0;
-                        let pixel = sprite[row] & (1 << col) != 0;
-                        let new_pixel = pixel ^ old_pixel;
-                        self.graphics.buffer[xx + yy * 64] = if new_pixel { 1 } else { 0 };
-                    }
+            }
+            Instruction::And { x, y } => {
+                self.registers[x as usize].v &= self.registers[y as usize].v;
+                if self.quirks.vf_reset {
+                    self.registers[0xF].v = 0;
                 }
-                if let Some(display) = display {
-                    display.draw(&self.graphics);
+            }
+            Instruction::Xor { x, y } => {
+                self.registers[x as usize].v ^= self.registers[y as usize].v;
+                if self.quirks.vf_reset {
+                    self.registers[0xF].v = 0;
                 }
             }
-            0xF => {
-                match self.nn {
-                    0x07 => {
-                        // set Vx to delay timer
-                        self.registers[self.x as usize].v = self.delay_timer as u8;
-                    }
-                    0x15 => {
-                        // set delay timer to Vx
-                        self.delay_timer = self.registers[self.x as usize].v as u16;
-                    }
-                    0x18 => {
-                        // set sound timer to Vx
-                        self.sound_timer = self.registers[self.x as usize].v as u16;
+            Instruction::AddRegisters { x, y } => {
+                let result =
+                    self.registers[x as usize].v as u16 + self.registers[y as usize].v as u16;
+                self.registers[x as usize].v = result as u8;
+                self.registers[0xF].v = if result > 0xFF { 1 } else { 0 };
+            }
+            Instruction::Subtract { x, y } => {
+                let (vx, vy) = (self.registers[x as usize].v, self.registers[y as usize].v);
+                self.registers[x as usize].v = vx.wrapping_sub(vy);
+                self.registers[0xF].v = if vx > vy { 1 } else { 0 };
+            }
+            Instruction::ShiftRight { x, y } => {
+                if self.quirks.shift_uses_vy {
+                    self.registers[x as usize].v = self.registers[y as usize].v;
+                }
+                let flag = self.registers[x as usize].v & 0x1;
+                self.registers[x as usize].v >>= 1;
+                self.registers[0xF].v = flag;
+            }
+            Instruction::SubtractReversed { x, y } => {
+                let (vx, vy) = (self.registers[x as usize].v, self.registers[y as usize].v);
+                self.registers[x as usize].v = vy.wrapping_sub(vx);
+                self.registers[0xF].v = if vy > vx { 1 } else { 0 };
+            }
+            Instruction::ShiftLeft { x, y } => {
+                if self.quirks.shift_uses_vy {
+                    self.registers[x as usize].v = self.registers[y as usize].v;
+                }
+                let flag = (self.registers[x as usize].v & 0x80) >> 7;
+                self.registers[x as usize].v <<= 1;
+                self.registers[0xF].v = flag;
+            }
+            Instruction::SkipNotEqualRegisters { x, y } => {
+                if self.registers[x as usize].v != self.registers[y as usize].v {
+                    self.skip_next();
+                }
+            }
+            Instruction::SetIndex(nnn) => {
+                self.index = nnn;
+            }
+            Instruction::JumpOffset(nnn) => {
+                // BXNN jumps to XNN + VX, BNNN to NNN + V0
+                let register = if self.quirks.jump_uses_vx {
+                    nnn as usize >> 8
+                } else {
+                    0
+                };
+                self.pc = nnn as usize + self.registers[register].v as usize;
+            }
+            Instruction::Random { x, nn } => {
+                self.registers[x as usize].v = self.rng.rand_u32() as u8 & nn;
+            }
+            Instruction::Draw { x, y, n } => {
+                if self.quirks.display_wait {
+                    if !self.vblank {
+                        // try again after the next vertical blank
+                        self.pc -= 2;
+                        return Ok(false);
                     }
-                    0x1E => {
-                        // add Vx to index
-                        self.index += self.registers[self.x as usize].v as u16;
-                        // TODO: VF is set to 1 when there is a range overflow (I + Vx > 0xFFF)
+                    self.vblank = false;
+                }
+                self.draw(x as usize, y as usize, n as usize)?;
+            }
+            Instruction::SkipKey(x) => {
+                if self.key_buffer.is_pressed(self.registers[x as usize].v) {
+                    self.skip_next();
+                }
+            }
+            Instruction::SkipNotKey(x) => {
+                if !self.key_buffer.is_pressed(self.registers[x as usize].v) {
+                    self.skip_next();
+                }
+            }
+            Instruction::LongIndex => {
+                // the 16 bit address follows the instruction
+                self.index = (self.read(self.pc)? as u16) << 8 | self.read(self.pc + 1)? as u16;
+                self.pc += 2;
+            }
+            Instruction::SelectPlanes(planes) => {
+                self.graphics.planes = planes & 0x3;
+            }
+            Instruction::LoadAudioPattern => {
+                let mut pattern = [0; 16];
+                for (i, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.read(self.index as usize + i)?;
+                }
+                self.audio_pattern = Some(pattern);
+            }
+            Instruction::GetDelay(x) => {
+                self.registers[x as usize].v = self.delay_timer as u8;
+            }
+            Instruction::WaitKey(x) => {
+                // wait for a key to be pressed and released
+                match self.waiting_key {
+                    Some(key) if !self.key_buffer.is_pressed(key) => {
+                        self.registers[x as usize].v = key;
+                        self.waiting_key = None;
                     }
-                    0x0A => {
-                        println!("Waiting for key press");
-                        let key_pressed = self.key_buffer.key.is_some();
-                        if key_pressed {
-                            let key_code = self.key_buffer.key.unwrap();
-                            println!("key pressed: {}", key_code);
-                            self.registers[self.x as usize].v = key_code;
-                        } else {
-                            self.pc -= 2;
-                        }
+                    Some(_) => {
+                        self.pc -= 2;
+                        return Ok(false);
                     }
-                    0x29 => {
-                        // set index to location of sprite for digit Vx
-                        self.index =
-                            self.registers[self.x as usize].v as u16 * 5 + FONT_BASE_ADDRESS as u16;
+                    None => {
+                        self.waiting_key = self.key_buffer.first_pressed();
+                        self.pc -= 2;
+                        return Ok(false);
                     }
-                    0x33 => {
-                        // store BCD representation of Vx in memory locations I, I+1, I+2
-                        let value = self.registers[self.x as usize].v;
-                        self.memory[self.index as usize] = value / 100;
-                        self.memory[self.index as usize + 1] = (value / 10) % 10;
-                        self.memory[self.index as usize + 2] = value % 10;
+                }
+            }
+            Instruction::SetDelay(x) => {
+                self.delay_timer = self.registers[x as usize].v as u16;
+            }
+            Instruction::SetSound(x) => {
+                self.sound_timer = self.registers[x as usize].v as u16;
+            }
+            Instruction::AddIndex(x) => {
+                self.index = self.index.wrapping_add(self.registers[x as usize].v as u16);
+                // TODO: VF is set to 1 when there is a range overflow (I + Vx > 0xFFF)
+            }
+            Instruction::SetPitch(x) => {
+                self.pitch = self.registers[x as usize].v;
+            }
+            Instruction::Font(x) => {
+                self.index = self.registers[x as usize].v as u16 * 5 + FONT_BASE_ADDRESS as u16;
+            }
+            Instruction::BigFont(x) => {
+                self.index =
+                    (self.registers[x as usize].v & 0xF) as u16 * 10 + BIG_FONT_BASE_ADDRESS as u16;
+            }
+            Instruction::Bcd(x) => {
+                let value = self.registers[x as usize].v;
+                self.write(self.index as usize, value / 100)?;
+                self.write(self.index as usize + 1, (value / 10) % 10)?;
+                self.write(self.index as usize + 2, value % 10)?;
+            }
+            Instruction::Store(x) => {
+                for i in 0..=x as usize {
+                    self.write(self.index as usize + i, self.registers[i].v)?;
+                }
+                self.increment_index(x);
+            }
+            Instruction::Load(x) => {
+                for i in 0..=x as usize {
+                    self.registers[i].v = self.read(self.index as usize + i)?;
+                }
+                self.increment_index(x);
+            }
+            Instruction::StoreFlags(x) => {
+                for i in 0..=x as usize {
+                    self.rpl[i] = self.registers[i].v;
+                }
+            }
+            Instruction::LoadFlags(x) => {
+                for i in 0..=x as usize {
+                    self.registers[i].v = self.rpl[i];
+                }
+            }
+        }
+        Ok(true)
+    }
+    /// Draws the sprite at I to the position in VX and VY, VF is set if a pixel was turned off.
+    fn draw(&mut self, x: usize, y: usize, n: usize) -> Result<(), Chip8Error> {
+        let (width, height) = (self.graphics.width(), self.graphics.height());
+        let x = self.registers[x].v as usize % width;
+        let y = self.registers[y].v as usize % height;
+        self.registers[0xF].v = 0;
+
+        // DXY0 draws a 16x16 sprite on SUPER-CHIP
+        let (rows, cols) = if n == 0 && self.platform.has_superchip() {
+            (16, 16)
+        } else {
+            (n, 8)
+        };
+        let bytes_per_row = cols / 8;
+
+        // every selected plane consumes its own sprite, one after the other
+        let mut sprite = self.index as usize;
+        for plane in [1, 2] {
+            if self.graphics.planes & plane == 0 {
+                continue;
+            }
+            for row in 0..rows {
+                let mut yy = row + y;
+                if yy >= height {
+                    if self.quirks.clip_sprites {
+                        break;
                     }
-                    0x55 => {
-                        // store V0 to Vx in memory starting at I
-                        for i in 0..=self.x {
-                            self.memory[self.index as usize + i as usize] =
-                                self.registers[i as usize].v;
+                    yy %= height;
+                }
+                let addr = sprite + row * bytes_per_row;
+                let line = if bytes_per_row == 2 {
+                    (self.read(addr)? as u16) << 8 | self.read(addr + 1)? as u16
+                } else {
+                    (self.read(addr)? as u16) << 8
+                };
+                for col in 0..cols {
+                    let mut xx = col + x;
+                    if xx >= width {
+                        if self.quirks.clip_sprites {
+                            break;
                         }
+                        xx %= width;
                     }
-                    0x65 => {
-                        // fill V0 to Vx with memory starting at I
-                        for i in 0..=self.x {
-                            self.registers[i as usize].v =
-                                self.memory[self.index as usize + i as usize];
-                        }
+                    if line & (0x8000 >> col) == 0 {
+                        continue;
                     }
-                    _ => {
-                        panic!(
-                            "Unknown instruction: 0x{:04x} at 0x{:04x}",
-                            self.instruction,
-                            self.pc - 2
-                        );
+                    if self.graphics.toggle(xx, yy, plane) {
+                        self.registers[0xF].v = 1;
                     }
                 }
             }
-            _ => {
-                panic!(
-                    "Unknown instruction: 0x{:04x} at 0x{:04x}",
-                    self.instruction,
-                    self.pc - 2
-                );
+            sprite += rows * bytes_per_row;
+        }
+        Ok(())
+    }
+    /// Executes one instruction. On error the program counter is left at the faulting instruction.
+    pub fn run(&mut self) -> Result<(), Chip8Error> {
+        self.fetch()?;
+        let instruction = self.decode();
+        // the trace shows the state before the instruction but only instructions that executed, not waits
+        let record = self.tracer.as_ref().and_then(|tracer| tracer.record(self));
+        match instruction.and_then(|instruction| self.execute(instruction)) {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(err) => {
+                self.pc = self.instruction_address;
+                return Err(err);
+            }
+        }
+        self.cycles += 1;
+        if let (Some(record), Some(mut tracer)) = (record, self.tracer.take()) {
+            match tracer.write(&record) {
+                Ok(()) => self.tracer = Some(tracer),
+                Err(err) => println!("Stopped tracing: {}", err),
+            }
+        }
+        Ok(())
+    }
+    /// Executes one instruction and ticks the timers once a frame worth of instructions has run.
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        self.run()?;
+        self.frame_cycle += 1;
+        if self.frame_cycle >= self.instructions_per_frame {
+            self.frame_cycle = 0;
+            self.tick_timers();
+        }
+        Ok(())
+    }
+    /// Runs the rest of the current frame, ending with a timer tick, or until the program exits.
+    pub fn run_frame(&mut self) -> Result<(), Chip8Error> {
+        loop {
+            self.step()?;
+            if self.frame_cycle == 0 || self.exited {
+                return Ok(());
+            }
+        }
+    }
+    /// Decrements the delay and sound timers and signals the vertical blank.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        self.vblank = true;
+    }
+    fn unknown_opcode(&self) -> Chip8Error {
+        Chip8Error::UnknownOpcode {
+            address: self.instruction_address,
+            opcode: self.instruction,
+        }
+    }
+    fn read(&self, target: usize) -> Result<u8, Chip8Error> {
+        self.memory
+            .get(target)
+            .copied()
+            .ok_or(Chip8Error::MemoryOutOfRange {
+                address: self.instruction_address,
+                opcode: self.instruction,
+                target,
+            })
+    }
+    fn write(&mut self, target: usize, value: u8) -> Result<(), Chip8Error> {
+        match self.memory.get_mut(target) {
+            Some(byte) => {
+                *byte = value;
+                Ok(())
             }
+            None => Err(Chip8Error::MemoryOutOfRange {
+                address: self.instruction_address,
+                opcode: self.instruction,
+                target,
+            }),
+        }
+    }
+    /// Skips the next instruction, the 4 byte F000 NNNN counts as one instruction on XO-CHIP.
+    fn skip_next(&mut self) {
+        if self.platform.has_xochip()
+            && self.memory.get(self.pc) == Some(&0xF0)
+            && self.memory.get(self.pc + 1) == Some(&0x00)
+        {
+            self.pc += 4;
+        } else {
+            self.pc += 2;
         }
     }
-    pub fn run(&mut self, display: Option<&mut Display>) {
-        // let start = std::time::Instant::now();
-        self.fetch();
-        self.decode();
-        self.execute(display);
-        thread::sleep(Duration::from_micros(SLEEP_MICROS));
-        // println!("Cycle took: {:?}", start.elapsed());
+    /// Sample rate of the XO-CHIP audio pattern in bits per second.
+    pub fn pattern_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+    /// What the buzzer should play, `None` while the sound timer is stopped.
+    pub fn sound(&self) -> Option<Sound> {
+        if self.sound_timer == 0 {
+            return None;
+        }
+        Some(match self.audio_pattern {
+            Some(pattern) => Sound::Pattern {
+                pattern,
+                rate: self.pattern_rate(),
+            },
+            None => Sound::Tone,
+        })
+    }
+    /// Moves I past the registers FX55/FX65 copied, as far as the quirks want.
+    fn increment_index(&mut self, x: u8) {
+        match self.quirks.index_increment {
+            IndexIncrement::None => {}
+            IndexIncrement::X => self.index = self.index.wrapping_add(x as u16),
+            IndexIncrement::XPlusOne => self.index = self.index.wrapping_add(x as u16 + 1),
+        }
     }
     pub fn clear_screen(&mut self) {
-        self.graphics.buffer = [0; 64 * 32];
+        self.graphics.clear();
+    }
+}
+
+/// Registers from VX to VY for 5XY2/5XY3, in reverse order if X > Y.
+fn register_range(x: u8, y: u8) -> Box<dyn Iterator<Item = usize>> {
+    let (x, y) = (x as usize, y as usize);
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
     }
 }
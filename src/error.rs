@@ -0,0 +1,215 @@
+use std::{fmt, path::PathBuf};
+
+/// Faults raised by the interpreter, `address` is the location of the faulting instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Chip8Error {
+    /// The opcode is not part of the instruction set of the selected platform.
+    UnknownOpcode { address: usize, opcode: u16 },
+    /// 00EE was executed with an empty stack.
+    StackUnderflow { address: usize, opcode: u16 },
+    /// 2NNN was executed with a full stack.
+    StackOverflow { address: usize, opcode: u16 },
+    /// The instruction accessed `target`, which lies outside of memory.
+    MemoryOutOfRange {
+        address: usize,
+        opcode: u16,
+        target: usize,
+    },
+    /// The program counter left memory.
+    PcOutOfRange { pc: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { address, opcode } => {
+                write!(
+                    f,
+                    "Unknown instruction: 0x{:04x} at 0x{:04x}",
+                    opcode, address
+                )
+            }
+            Chip8Error::StackUnderflow { address, opcode } => write!(
+                f,
+                "Stack underflow: 0x{:04x} at 0x{:04x} returned with an empty stack",
+                opcode, address
+            ),
+            Chip8Error::StackOverflow { address, opcode } => write!(
+                f,
+                "Stack overflow: 0x{:04x} at 0x{:04x} called with a full stack",
+                opcode, address
+            ),
+            Chip8Error::MemoryOutOfRange {
+                address,
+                opcode,
+                target,
+            } => write!(
+                f,
+                "Memory out of range: 0x{:04x} at 0x{:04x} accessed 0x{:04x}",
+                opcode, address, target
+            ),
+            Chip8Error::PcOutOfRange { pc } => {
+                write!(f, "Program counter out of bounds: 0x{:04x}", pc)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+/// The opcode is not an instruction of any platform.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown opcode: 0x{:04x}", self.opcode)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Reasons a save state cannot be written or restored.
+#[derive(Debug)]
+pub enum StateError {
+    /// The data does not start with the save state magic.
+    BadMagic,
+    /// The save state was written by a newer or unknown format version.
+    UnsupportedVersion(u16),
+    /// The payload does not match the checksum in the header.
+    ChecksumMismatch,
+    /// The data ended before all fields were read.
+    Truncated,
+    /// The save state was made for a different platform than the running one.
+    PlatformMismatch,
+    /// A field holds a value the machine cannot have.
+    Invalid(&'static str),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "Not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported save state version {}", version)
+            }
+            StateError::ChecksumMismatch => write!(f, "Save state checksum mismatch"),
+            StateError::Truncated => write!(f, "Save state is truncated"),
+            StateError::PlatformMismatch => {
+                write!(f, "Save state was made for a different platform")
+            }
+            StateError::Invalid(field) => write!(f, "Save state has an invalid {}", field),
+            StateError::Io(err) => write!(f, "Save state I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<std::io::Error> for StateError {
+    fn from(err: std::io::Error) -> Self {
+        StateError::Io(err)
+    }
+}
+
+/// Reasons a movie cannot be read or written.
+#[derive(Debug)]
+pub enum MovieError {
+    /// The data does not start with the movie magic.
+    BadMagic,
+    /// The movie was written by a newer or unknown format version.
+    UnsupportedVersion(u16),
+    /// The header holds a platform or quirk value this version does not know.
+    InvalidHeader,
+    /// The data ended before all frames were read.
+    Truncated,
+    Io(std::io::Error),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::BadMagic => write!(f, "Not a movie"),
+            MovieError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported movie version {}", version)
+            }
+            MovieError::InvalidHeader => write!(f, "Movie header is invalid"),
+            MovieError::Truncated => write!(f, "Movie is truncated"),
+            MovieError::Io(err) => write!(f, "Movie I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<std::io::Error> for MovieError {
+    fn from(err: std::io::Error) -> Self {
+        MovieError::Io(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file is not valid TOML.
+    Parse(String),
+    /// A setting is unknown or has a value of the wrong kind.
+    Invalid {
+        key: String,
+        message: String,
+    },
+    /// The error of a config file, with its path.
+    File(PathBuf, Box<ConfigError>),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Parse(err) => write!(f, "Config file is invalid: {}", err),
+            ConfigError::Invalid { key, message } => {
+                write!(f, "Config setting {} {}", key, message)
+            }
+            ConfigError::File(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Io(err) => write!(f, "Config file I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(err: std::io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+/// Reasons a ROM database cannot be read.
+#[derive(Debug)]
+pub enum DatabaseError {
+    /// A file is not valid JSON.
+    Parse(String),
+    /// A file does not have the layout of the chip-8-database.
+    Invalid(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::Parse(err) => write!(f, "Database is not valid JSON: {}", err),
+            DatabaseError::Invalid(message) => write!(f, "Database is invalid: {}", message),
+            DatabaseError::Io(err) => write!(f, "Database I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<std::io::Error> for DatabaseError {
+    fn from(err: std::io::Error) -> Self {
+        DatabaseError::Io(err)
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{
+    audio::Sound,
+    emulator::{Graphics, KeyState},
+};
+
+/// Shows the frame buffer to the user.
+pub trait VideoSink {
+    fn present(&mut self, graphics: &Graphics);
+}
+
+/// Plays the buzzer.
+pub trait AudioSink {
+    /// Called once per frame with the sound to play while the sound timer is running, `None` otherwise.
+    fn set_playing(&mut self, sound: Option<Sound>);
+}
+
+/// Emulator controls bound to host keys outside of the keypad.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Hotkey {
+    Quit,
+    /// Held down (true) or released (false).
+    FastForward(bool),
+    /// Held down (true) or released (false).
+    SlowMotion(bool),
+    /// Held down (true) or released (false).
+    Rewind(bool),
+    /// Save the machine to the selected slot.
+    SaveState,
+    /// Restore the machine from the selected slot.
+    LoadState,
+    /// Select the next save state slot.
+    NextSlot,
+    /// Write the framebuffer to an image next to the ROM.
+    Screenshot,
+    /// Start or stop recording video next to the ROM.
+    ToggleVideo,
+}
+
+/// Feeds host input into the keypad.
+pub trait InputSource {
+    /// Applies pending host events to `keys` and returns the hotkeys that were triggered.
+    fn poll(&mut self, keys: &mut KeyState) -> Vec<Hotkey>;
+}
+
+/// Everything the run loops of the emulator and the debugger need from the host.
+pub trait Frontend: VideoSink + AudioSink + InputSource {}
+
+impl<T: VideoSink + AudioSink + InputSource> Frontend for T {}
+
+/// Frontend without any output, for tests and tooling.
+#[derive(Default)]
+pub struct Headless {
+    /// Number of frames presented so far.
+    pub frames: u64,
+    /// The last frame presented.
+    pub graphics: Graphics,
+    /// Keys reported as held down on every poll.
+    pub keys: KeyState,
+}
+
+impl Headless {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl VideoSink for Headless {
+    fn present(&mut self, graphics: &Graphics) {
+        self.frames += 1;
+        self.graphics = graphics.clone();
+    }
+}
+
+impl AudioSink for Headless {
+    fn set_playing(&mut self, _sound: Option<Sound>) {}
+}
+
+impl InputSource for Headless {
+    fn poll(&mut self, keys: &mut KeyState) -> Vec<Hotkey> {
+        *keys = self.keys;
+        Vec::new()
+    }
+}
@@ -0,0 +1,468 @@
+//! Instructions of CHIP-8, SUPER-CHIP and XO-CHIP decoded from their 16 bit opcodes.
+//!
+//! The interpreter, the disassembler, the debugger and the tracer all work on [`Instruction`]. Every instruction
+//! is one row of the table at the end of this file, which gives its opcode pattern, the platform that introduced
+//! it, where the program goes after it, and how it is written in each [`Syntax`]. Nibbles of an opcode pattern are
+//! named like this:
+//!
+//! ```text
+//! 0xDXYN    X, Y: registers, N: 4 bit constant
+//! 0x7XNN    NN: 8 bit constant
+//! 0x1NNN    NNN: 12 bit address
+//! ```
+//!
+//! Hex digits of a pattern have to match, the letters are operands. A letter without a field in the row, like the N
+//! of 9XYN, is ignored when decoding and written as 0 when encoding.
+
+use std::fmt;
+
+use crate::{
+    error::DecodeError,
+    platform::Platform,
+    quirks::{IndexIncrement, Quirks},
+};
+
+/// How instructions are written out.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Syntax {
+    /// Plain English that spells out the quirks, like `set V0 to V0 | V1 and reset VF`.
+    #[default]
+    Description,
+    /// The mnemonics of Cowgod's technical reference, like `OR V0, V1`.
+    Classic,
+    /// The statements of Octo, like `v0 |= v1`.
+    Octo,
+}
+
+/// Where the program goes after an instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Flow {
+    /// Continues with the next instruction.
+    Next,
+    /// Continues with the next instruction or the one after it.
+    Skip,
+    Jump(u16),
+    /// Runs the subroutine at the address, which returns to the next instruction.
+    Call(u16),
+    /// Jumps to the address plus a register, to an entry of a jump table.
+    JumpTable(u16),
+    /// Returns from a subroutine or exits, the following bytes are not reached from here.
+    Stop,
+}
+
+pub const SYNTAXES: [&str; 3] = ["description", "classic", "octo"];
+
+impl Syntax {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "description" => Some(Syntax::Description),
+            "classic" => Some(Syntax::Classic),
+            "octo" => Some(Syntax::Octo),
+            _ => None,
+        }
+    }
+}
+
+/// Bits of `pattern` that have to match and their value, e.g. `(0xF00F, 0x8004)` for `8XY4`.
+const fn fixed_bits(pattern: &str) -> (u16, u16) {
+    let digits = pattern.as_bytes();
+    let (mut mask, mut value, mut i) = (0, 0, 0);
+    while i < 4 {
+        let digit = match digits[i] {
+            b'0'..=b'9' => Some(digits[i] - b'0'),
+            b'A'..=b'F' => Some(digits[i] - b'A' + 10),
+            _ => None,
+        };
+        mask <<= 4;
+        value <<= 4;
+        if let Some(digit) = digit {
+            mask |= 0xF;
+            value |= digit as u16;
+        }
+        i += 1;
+    }
+    (mask, value)
+}
+
+/// Type and position of an operand field.
+macro_rules! operand {
+    (type nnn) => {
+        u16
+    };
+    (type $field:ident) => {
+        u8
+    };
+    (shift x) => {
+        8
+    };
+    (shift y) => {
+        4
+    };
+    (shift $field:ident) => {
+        0
+    };
+    (mask n) => {
+        0xF
+    };
+    (mask nn) => {
+        0xFF
+    };
+    (mask nnn) => {
+        0xFFF
+    };
+    (mask $field:ident) => {
+        0xF
+    };
+}
+
+/// Defines [`Instruction`] and everything that depends on the opcode from a table with one row per instruction:
+///
+/// ```text
+/// Variant { fields } = "pattern", platform that introduced it, flow,
+///     classic mnemonic, Octo statement, description;
+/// ```
+///
+/// The mnemonic and the statement are format strings of the fields, the flow and the description are expressions of
+/// them and of the `platform` and `quirks` named at the top.
+macro_rules! instructions {
+    (
+        |$platform:ident, $quirks:ident|
+        $(
+            $(#[$attr:meta])*
+            $name:ident $(($arg:ident))? $({ $($field:ident),+ })? = $pattern:literal, $introduced:ident, $flow:expr,
+                $classic:literal, $octo:literal, $description:expr;
+        )+
+    ) => {
+        #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+        pub enum Instruction {
+            $(
+                $(#[$attr])*
+                $name $((operand!(type $arg)))? $({ $($field: operand!(type $field)),+ })?,
+            )+
+        }
+
+        impl Instruction {
+            pub fn decode(opcode: u16) -> Result<Self, DecodeError> {
+                $(
+                    let (mask, value) = const { fixed_bits($pattern) };
+                    if opcode & mask == value {
+                        return Ok(Instruction::$name
+                            $((((opcode >> operand!(shift $arg)) & operand!(mask $arg)) as operand!(type $arg)))?
+                            $({ $($field: ((opcode >> operand!(shift $field)) & operand!(mask $field))
+                                as operand!(type $field)),+ })?);
+                    }
+                )+
+                Err(DecodeError { opcode })
+            }
+            /// Opcode of the instruction, the inverse of [`Instruction::decode`] apart from the N of 9XYN.
+            pub fn encode(&self) -> u16 {
+                match *self {
+                    $(
+                        Instruction::$name $(($arg))? $({ $($field),+ })? => {
+                            let (_, value) = const { fixed_bits($pattern) };
+                            value
+                                $(| ($arg as u16) << operand!(shift $arg))?
+                                $($(| ($field as u16) << operand!(shift $field))+)?
+                        }
+                    )+
+                }
+            }
+            /// The opcode pattern, like `8XY4`.
+            pub fn pattern(&self) -> &'static str {
+                match self {
+                    $(Instruction::$name { .. } => $pattern,)+
+                }
+            }
+            /// The platform that introduced the instruction.
+            fn introduced_on(&self) -> Platform {
+                match self {
+                    $(Instruction::$name { .. } => Platform::$introduced,)+
+                }
+            }
+            #[allow(unused_variables)]
+            pub fn flow(&self) -> Flow {
+                match *self {
+                    $(Instruction::$name $(($arg))? $({ $($field),+ })? => $flow,)+
+                }
+            }
+            /// The instruction as an Octo statement. Octo writes skips as the condition under which the next
+            /// instruction runs.
+            pub fn octo(&self) -> String {
+                match *self {
+                    $(Instruction::$name $(($arg))? $({ $($field),+ })? => format!($octo),)+
+                }
+            }
+            /// What the instruction does in words.
+            #[allow(unused_variables)]
+            pub fn describe(&self, $platform: Platform, $quirks: &Quirks) -> String {
+                match *self {
+                    $(Instruction::$name $(($arg))? $({ $($field),+ })? => String::from($description),)+
+                }
+            }
+        }
+
+        /// Writes the classic mnemonics, e.g. `LD V0, 0x12` or `DRW V0, V1, 5`.
+        impl fmt::Display for Instruction {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match *self {
+                    $(Instruction::$name $(($arg))? $({ $($field),+ })? => write!(f, $classic),)+
+                }
+            }
+        }
+    };
+}
+
+impl Instruction {
+    /// Whether the interpreters of `platform` know the instruction.
+    pub fn available_on(&self, platform: Platform) -> bool {
+        match self.introduced_on() {
+            Platform::Chip8 => true,
+            Platform::SuperChip => platform.has_superchip(),
+            Platform::XoChip => platform.has_xochip(),
+        }
+    }
+    /// The address operand of jumps, calls and ANNN.
+    pub fn address(&self) -> Option<u16> {
+        self.pattern()
+            .ends_with("NNN")
+            .then(|| self.encode() & 0x0FFF)
+    }
+    /// Size in bytes, including the address following F000.
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LongIndex => 4,
+            _ => 2,
+        }
+    }
+    /// The instruction written in `syntax`. Descriptions depend on the platform and the quirks.
+    pub fn format(&self, syntax: Syntax, platform: Platform, quirks: &Quirks) -> String {
+        match syntax {
+            Syntax::Description => self.describe(platform, quirks),
+            Syntax::Classic => self.to_string(),
+            Syntax::Octo => self.octo(),
+        }
+    }
+}
+
+fn vf_reset(quirks: &Quirks) -> &'static str {
+    if quirks.vf_reset {
+        " and reset VF"
+    } else {
+        ""
+    }
+}
+
+fn index_increment(quirks: &Quirks) -> &'static str {
+    match quirks.index_increment {
+        IndexIncrement::None => "",
+        IndexIncrement::X => ", then add X to I",
+        IndexIncrement::XPlusOne => ", then add X + 1 to I",
+    }
+}
+
+fn describe_draw(platform: Platform, quirks: &Quirks, x: u8, y: u8, n: u8) -> String {
+    let size = if n == 0 && platform.has_superchip() {
+        "16x16".to_owned()
+    } else {
+        format!("0x{:X}", n)
+    };
+    format!(
+        "display at V{:X}, V{:X}, {}{}{}",
+        x,
+        y,
+        size,
+        if quirks.clip_sprites {
+            ", clipped at the edges"
+        } else {
+            ", wrapping around the edges"
+        },
+        if quirks.display_wait {
+            ", after vblank"
+        } else {
+            ""
+        }
+    )
+}
+
+#[rustfmt::skip]
+instructions! {
+    |platform, quirks|
+
+    /// 00E0
+    ClearScreen = "00E0", Chip8, Flow::Next,
+        "CLS", "clear", "clear screen";
+    /// 00EE
+    Return = "00EE", Chip8, Flow::Stop,
+        "RET", "return", "return from subroutine";
+    /// 00CN, SUPER-CHIP
+    ScrollDown(n) = "00CN", SuperChip, Flow::Next,
+        "SCD {n}", "scroll-down {n}", format!("scroll down {n} pixels");
+    /// 00DN, XO-CHIP
+    ScrollUp(n) = "00DN", XoChip, Flow::Next,
+        "SCU {n}", "scroll-up {n}", format!("scroll up {n} pixels");
+    /// 00FB, SUPER-CHIP
+    ScrollRight = "00FB", SuperChip, Flow::Next,
+        "SCR", "scroll-right", "scroll right 4 pixels";
+    /// 00FC, SUPER-CHIP
+    ScrollLeft = "00FC", SuperChip, Flow::Next,
+        "SCL", "scroll-left", "scroll left 4 pixels";
+    /// 00FD, SUPER-CHIP
+    Exit = "00FD", SuperChip, Flow::Stop,
+        "EXIT", "exit", "exit";
+    /// 00FE, SUPER-CHIP
+    LowRes = "00FE", SuperChip, Flow::Next,
+        "LOW", "lores", "switch to low resolution";
+    /// 00FF, SUPER-CHIP
+    HighRes = "00FF", SuperChip, Flow::Next,
+        "HIGH", "hires", "switch to high resolution";
+    /// 1NNN
+    Jump(nnn) = "1NNN", Chip8, Flow::Jump(nnn),
+        "JP 0x{nnn:03X}", "jump 0x{nnn:03X}", format!("jump to 0x{nnn:03X}");
+    /// 2NNN
+    Call(nnn) = "2NNN", Chip8, Flow::Call(nnn),
+        "CALL 0x{nnn:03X}", ":call 0x{nnn:03X}", format!("call subroutine at 0x{nnn:03X}");
+    /// 3XNN
+    SkipEqual { x, nn } = "3XNN", Chip8, Flow::Skip,
+        "SE V{x:X}, 0x{nn:02X}", "if v{x:X} != 0x{nn:02X} then",
+        format!("skip next instruction if V{x:X} == 0x{nn:02x}");
+    /// 4XNN
+    SkipNotEqual { x, nn } = "4XNN", Chip8, Flow::Skip,
+        "SNE V{x:X}, 0x{nn:02X}", "if v{x:X} == 0x{nn:02X} then",
+        format!("skip next instruction if V{x:X} != 0x{nn:02x}");
+    /// 5XY0
+    SkipEqualRegisters { x, y } = "5XY0", Chip8, Flow::Skip,
+        "SE V{x:X}, V{y:X}", "if v{x:X} != v{y:X} then",
+        format!("skip next instruction if V{x:X} == V{y:X}");
+    /// 5XY2, XO-CHIP
+    StoreRange { x, y } = "5XY2", XoChip, Flow::Next,
+        "LD [I], V{x:X}-V{y:X}", "save v{x:X} - v{y:X}",
+        format!("store V{x:X} to V{y:X} in memory starting at I");
+    /// 5XY3, XO-CHIP
+    LoadRange { x, y } = "5XY3", XoChip, Flow::Next,
+        "LD V{x:X}-V{y:X}, [I]", "load v{x:X} - v{y:X}",
+        format!("fill V{x:X} to V{y:X} with memory starting at I");
+    /// 6XNN
+    Set { x, nn } = "6XNN", Chip8, Flow::Next,
+        "LD V{x:X}, 0x{nn:02X}", "v{x:X} := 0x{nn:02X}", format!("set V{x:X} to 0x{nn:02x}");
+    /// 7XNN
+    Add { x, nn } = "7XNN", Chip8, Flow::Next,
+        "ADD V{x:X}, 0x{nn:02X}", "v{x:X} += 0x{nn:02X}", format!("add 0x{nn:02x} to V{x:X}");
+    /// 8XY0
+    Copy { x, y } = "8XY0", Chip8, Flow::Next,
+        "LD V{x:X}, V{y:X}", "v{x:X} := v{y:X}", format!("set V{x:X} to V{y:X}");
+    /// 8XY1
+    Or { x, y } = "8XY1", Chip8, Flow::Next,
+        "OR V{x:X}, V{y:X}", "v{x:X} |= v{y:X}",
+        format!("set V{x:X} to V{x:X} | V{y:X}{}", vf_reset(quirks));
+    /// 8XY2
+    And { x, y } = "8XY2", Chip8, Flow::Next,
+        "AND V{x:X}, V{y:X}", "v{x:X} &= v{y:X}",
+        format!("set V{x:X} to V{x:X} & V{y:X}{}", vf_reset(quirks));
+    /// 8XY3
+    Xor { x, y } = "8XY3", Chip8, Flow::Next,
+        "XOR V{x:X}, V{y:X}", "v{x:X} ^= v{y:X}",
+        format!("set V{x:X} to V{x:X} ^ V{y:X}{}", vf_reset(quirks));
+    /// 8XY4
+    AddRegisters { x, y } = "8XY4", Chip8, Flow::Next,
+        "ADD V{x:X}, V{y:X}", "v{x:X} += v{y:X}", format!("add V{x:X} to V{y:X}");
+    /// 8XY5
+    Subtract { x, y } = "8XY5", Chip8, Flow::Next,
+        "SUB V{x:X}, V{y:X}", "v{x:X} -= v{y:X}", format!("subtract V{y:X} from V{x:X}");
+    /// 8XY6
+    ShiftRight { x, y } = "8XY6", Chip8, Flow::Next,
+        "SHR V{x:X}, V{y:X}", "v{x:X} >>= v{y:X}",
+        if quirks.shift_uses_vy {
+            format!("set V{x:X} to V{y:X} shifted right")
+        } else {
+            format!("shift V{x:X} right")
+        };
+    /// 8XY7
+    SubtractReversed { x, y } = "8XY7", Chip8, Flow::Next,
+        "SUBN V{x:X}, V{y:X}", "v{x:X} =- v{y:X}", format!("subtract V{x:X} from V{y:X}");
+    /// 8XYE
+    ShiftLeft { x, y } = "8XYE", Chip8, Flow::Next,
+        "SHL V{x:X}, V{y:X}", "v{x:X} <<= v{y:X}",
+        if quirks.shift_uses_vy {
+            format!("set V{x:X} to V{y:X} shifted left")
+        } else {
+            format!("shift V{x:X} left")
+        };
+    /// 9XY0, N is ignored like the interpreters always did
+    SkipNotEqualRegisters { x, y } = "9XYN", Chip8, Flow::Skip,
+        "SNE V{x:X}, V{y:X}", "if v{x:X} == v{y:X} then",
+        format!("skip next instruction if V{x:X} != V{y:X}");
+    /// ANNN
+    SetIndex(nnn) = "ANNN", Chip8, Flow::Next,
+        "LD I, 0x{nnn:03X}", "i := 0x{nnn:03X}", format!("set index to 0x{nnn:03X}");
+    /// BNNN, read as BXNN with the `jump_uses_vx` quirk.
+    JumpOffset(nnn) = "BNNN", Chip8, Flow::JumpTable(nnn),
+        "JP V0, 0x{nnn:03X}", "jump0 0x{nnn:03X}",
+        if quirks.jump_uses_vx {
+            format!("jump to 0x{nnn:03X} + V{:X}", nnn >> 8)
+        } else {
+            format!("jump to 0x{nnn:03X} + V0")
+        };
+    /// CXNN
+    Random { x, nn } = "CXNN", Chip8, Flow::Next,
+        "RND V{x:X}, 0x{nn:02X}", "v{x:X} := random 0x{nn:02X}",
+        format!("set V{x:X} to random number & 0x{nn:02x}");
+    /// DXYN
+    Draw { x, y, n } = "DXYN", Chip8, Flow::Next,
+        "DRW V{x:X}, V{y:X}, {n}", "sprite v{x:X} v{y:X} {n}", describe_draw(platform, quirks, x, y, n);
+    /// EX9E
+    SkipKey(x) = "EX9E", Chip8, Flow::Skip,
+        "SKP V{x:X}", "if v{x:X} -key then", format!("skip next instruction if key V{x:X} is pressed");
+    /// EXA1
+    SkipNotKey(x) = "EXA1", Chip8, Flow::Skip,
+        "SKNP V{x:X}", "if v{x:X} key then", format!("skip next instruction if key V{x:X} is not pressed");
+    /// F000 NNNN, XO-CHIP. The address is the next word in memory, which makes this the only 4 byte instruction.
+    LongIndex = "F000", XoChip, Flow::Next,
+        "LD I, LONG", "i := long", "set index to the following 16 bit address";
+    /// FN01, XO-CHIP
+    SelectPlanes(x) = "FX01", XoChip, Flow::Next,
+        "PLANE {x}", "plane {x}", format!("select bitplanes 0x{:X}", x & 0x3);
+    /// F002, XO-CHIP
+    LoadAudioPattern = "F002", XoChip, Flow::Next,
+        "AUDIO", "audio", "load audio pattern from memory starting at I";
+    /// FX07
+    GetDelay(x) = "FX07", Chip8, Flow::Next,
+        "LD V{x:X}, DT", "v{x:X} := delay", format!("set V{x:X} to delay timer");
+    /// FX0A
+    WaitKey(x) = "FX0A", Chip8, Flow::Next,
+        "LD V{x:X}, K", "v{x:X} := key", format!("wait for key press and release and store in V{x:X}");
+    /// FX15
+    SetDelay(x) = "FX15", Chip8, Flow::Next,
+        "LD DT, V{x:X}", "delay := v{x:X}", format!("set delay timer to V{x:X}");
+    /// FX18
+    SetSound(x) = "FX18", Chip8, Flow::Next,
+        "LD ST, V{x:X}", "buzzer := v{x:X}", format!("set sound timer to V{x:X}");
+    /// FX1E
+    AddIndex(x) = "FX1E", Chip8, Flow::Next,
+        "ADD I, V{x:X}", "i += v{x:X}", format!("add V{x:X} to index");
+    /// FX29
+    Font(x) = "FX29", Chip8, Flow::Next,
+        "LD F, V{x:X}", "i := hex v{x:X}", format!("set index to location of sprite for digit V{x:X}");
+    /// FX30, SUPER-CHIP
+    BigFont(x) = "FX30", SuperChip, Flow::Next,
+        "LD HF, V{x:X}", "i := bighex v{x:X}", format!("set index to location of big sprite for digit V{x:X}");
+    /// FX33
+    Bcd(x) = "FX33", Chip8, Flow::Next,
+        "LD B, V{x:X}", "bcd v{x:X}", format!("store BCD representation of V{x:X} in memory locations I, I+1, I+2");
+    /// FX3A, XO-CHIP
+    SetPitch(x) = "FX3A", XoChip, Flow::Next,
+        "PITCH V{x:X}", "pitch := v{x:X}", format!("set audio pitch to V{x:X}");
+    /// FX55
+    Store(x) = "FX55", Chip8, Flow::Next,
+        "LD [I], V{x:X}", "save v{x:X}",
+        format!("store V0 to V{x:X} in memory starting at I{}", index_increment(quirks));
+    /// FX65
+    Load(x) = "FX65", Chip8, Flow::Next,
+        "LD V{x:X}, [I]", "load v{x:X}",
+        format!("fill V0 to V{x:X} with memory starting at I{}", index_increment(quirks));
+    /// FX75, SUPER-CHIP
+    StoreFlags(x) = "FX75", SuperChip, Flow::Next,
+        "LD R, V{x:X}", "saveflags v{x:X}", format!("store V0 to V{x:X} in RPL user flags");
+    /// FX85, SUPER-CHIP
+    LoadFlags(x) = "FX85", SuperChip, Flow::Next,
+        "LD V{x:X}, R", "loadflags v{x:X}", format!("fill V0 to V{x:X} from RPL user flags");
+}
@@ -0,0 +1,235 @@
+//! Bindings of host keys to the 16 keys of the CHIP-8 keypad.
+//!
+//! The keypad of the COSMAC VIP is laid out like this, the presets put it on the left of the keyboard:
+//!
+//! ```text
+//! 1 2 3 C        1 2 3 4
+//! 4 5 6 D   <-   Q W E R
+//! 7 8 9 E        A S D F
+//! A 0 B F        Y X C V   (QWERTZ)
+//! ```
+//!
+//! Host keys are named like SDL names them, e.g. `Q`, `Space` or `Keypad 7`.
+//!
+//! Game controller buttons use the names of SDL's controller API: `a`, `b`, `x`, `y`, `back`, `start`,
+//! `leftshoulder`, `rightshoulder`, `dpup`, `dpdown`, `dpleft` and `dpright`. The left stick acts as the d-pad.
+
+use crate::emulator::KeyState;
+
+/// CHIP-8 keys in the order they appear on the keypad, row by row.
+pub const KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+pub const PRESETS: [&str; 4] = ["qwerty", "qwertz", "azerty", "numpad"];
+
+const QWERTY: [&str; 16] = [
+    "1", "2", "3", "4", "Q", "W", "E", "R", "A", "S", "D", "F", "Z", "X", "C", "V",
+];
+const QWERTZ: [&str; 16] = [
+    "1", "2", "3", "4", "Q", "W", "E", "R", "A", "S", "D", "F", "Y", "X", "C", "V",
+];
+const AZERTY: [&str; 16] = [
+    "1", "2", "3", "4", "A", "Z", "E", "R", "Q", "S", "D", "F", "W", "X", "C", "V",
+];
+/// The digits keep their value, A-F go on the keys around them.
+const NUMPAD: [&str; 16] = [
+    "Keypad 1",
+    "Keypad 2",
+    "Keypad 3",
+    "Keypad /",
+    "Keypad 4",
+    "Keypad 5",
+    "Keypad 6",
+    "Keypad *",
+    "Keypad 7",
+    "Keypad 8",
+    "Keypad 9",
+    "Keypad -",
+    "Keypad .",
+    "Keypad 0",
+    "Keypad Enter",
+    "Keypad +",
+];
+
+/// Host keys the SDL frontend reserves for its controls, they cannot press keypad keys.
+pub const HOTKEYS: [(&str, &str); 11] = [
+    ("Escape", "quit"),
+    ("Tab", "fast-forward"),
+    ("Left Shift", "slow motion"),
+    ("Backspace", "rewind"),
+    ("M", "mute"),
+    ("F5", "save state"),
+    ("F6", "next save state slot"),
+    ("F7", "load state"),
+    ("F9", "record video"),
+    ("F11", "fullscreen"),
+    ("F12", "screenshot"),
+];
+
+/// Most games steer with 2, 4, 6 and 8 and act with 5, the other buttons cover the remaining keys.
+const CONTROLLER: [(&str, u8); 12] = [
+    ("dpup", 0x2),
+    ("dpdown", 0x8),
+    ("dpleft", 0x4),
+    ("dpright", 0x6),
+    ("a", 0x5),
+    ("b", 0x0),
+    ("x", 0x7),
+    ("y", 0x9),
+    ("leftshoulder", 0x1),
+    ("rightshoulder", 0x3),
+    ("back", 0xA),
+    ("start", 0xF),
+];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Keymap {
+    /// Host key names and the CHIP-8 key they press, a CHIP-8 key may appear several times.
+    pub bindings: Vec<(String, u8)>,
+}
+
+impl Keymap {
+    /// QWERTZ layout, the default.
+    pub fn new() -> Self {
+        Self::from_preset("qwertz").unwrap()
+    }
+    pub fn from_preset(name: &str) -> Option<Self> {
+        let layout = match name {
+            "qwerty" => QWERTY,
+            "qwertz" => QWERTZ,
+            "azerty" => AZERTY,
+            "numpad" => NUMPAD,
+            _ => return None,
+        };
+        Some(Keymap {
+            bindings: layout
+                .iter()
+                .zip(KEYPAD)
+                .map(|(host, key)| (host.to_string(), key))
+                .collect(),
+        })
+    }
+    /// Default bindings of the buttons of a game controller.
+    pub fn controller() -> Self {
+        Keymap {
+            bindings: CONTROLLER
+                .iter()
+                .map(|&(button, key)| (button.to_string(), key))
+                .collect(),
+        }
+    }
+    /// Replaces the host keys of `key` with `hosts`, which no longer press the keys they pressed before.
+    pub fn bind(&mut self, key: u8, hosts: &[&str]) {
+        self.bindings.retain(|(name, k)| {
+            *k != key && !hosts.iter().any(|host| host.eq_ignore_ascii_case(name))
+        });
+        self.bindings
+            .extend(hosts.iter().map(|host| (host.to_string(), key)));
+    }
+    /// Makes `host` press `key` as well as the other host keys of `key`, instead of what it pressed before.
+    pub fn add(&mut self, key: u8, host: &str) {
+        self.bindings
+            .retain(|(name, _)| !name.eq_ignore_ascii_case(host));
+        self.bindings.push((host.to_string(), key));
+    }
+    /// CHIP-8 key bound to the host key, names are compared without regard to case.
+    pub fn key(&self, host: &str) -> Option<u8> {
+        self.bindings
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(host))
+            .map(|&(_, key)| key)
+    }
+    /// Host keys bound to the CHIP-8 key.
+    pub fn hosts(&self, key: u8) -> Vec<&str> {
+        self.bindings
+            .iter()
+            .filter(|&&(_, k)| k == key)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Device a held host input belongs to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Source {
+    Keyboard,
+    /// Buttons of the game controller with this SDL instance id.
+    Buttons(u32),
+    /// Left stick of the game controller with this SDL instance id, it presses the keys of the d-pad.
+    Stick(u32),
+}
+
+/// Host inputs held down and the CHIP-8 key each of them presses.
+///
+/// Several host keys and buttons, on several devices, can press one CHIP-8 key. The key stays pressed until the
+/// last of them is released.
+#[derive(Clone, Debug, Default)]
+pub struct HeldInputs {
+    held: Vec<(Source, String, u8)>,
+}
+
+impl HeldInputs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Presses `key` for the host input `input` of `source`, repeated presses of a held input count once.
+    pub fn press(&mut self, source: Source, input: &str, key: u8, keys: &mut KeyState) {
+        if !self
+            .held
+            .iter()
+            .any(|(s, i, k)| *s == source && i == input && *k == key)
+        {
+            self.held.push((source, input.to_string(), key));
+        }
+        keys.press(key);
+    }
+    /// Releases the host input `input` of `source`, `key` is released once no other input holds it.
+    pub fn release(&mut self, source: Source, input: &str, key: u8, keys: &mut KeyState) {
+        self.held
+            .retain(|(s, i, k)| !(*s == source && i == input && *k == key));
+        if self.count(key) == 0 {
+            keys.release(key);
+        }
+    }
+    /// Releases everything the buttons and the stick of the controller `id` hold, before it is unplugged.
+    pub fn disconnect(&mut self, id: u32, keys: &mut KeyState) {
+        let (gone, held) = self.held.drain(..).partition(
+            |(source, ..)| matches!(source, Source::Buttons(i) | Source::Stick(i) if *i == id),
+        );
+        self.held = held;
+        for (.., key) in gone {
+            if self.count(key) == 0 {
+                keys.release(key);
+            }
+        }
+    }
+    /// Number of host inputs holding `key`.
+    pub fn count(&self, key: u8) -> usize {
+        self.held.iter().filter(|&&(.., k)| k == key).count()
+    }
+}
+
+/// What the host key does if it is one of the [`HOTKEYS`].
+pub fn hotkey(host: &str) -> Option<&'static str> {
+    HOTKEYS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(host))
+        .map(|&(_, action)| action)
+}
+
+/// Parses a binding like `5=Space` or `A=Z,Keypad 0` into the CHIP-8 key and its host keys.
+pub fn parse_binding(binding: &str) -> Option<(u8, Vec<&str>)> {
+    let (key, hosts) = binding.split_once('=')?;
+    let key = u8::from_str_radix(key.trim(), 16)
+        .ok()
+        .filter(|&k| k < 16)?;
+    let hosts: Vec<_> = hosts.split(',').map(str::trim).collect();
+    (!hosts.iter().any(|host| host.is_empty())).then_some((key, hosts))
+}
@@ -1,11 +1,30 @@
-use consts::FONT_BASE_ADDRESS;
-use font::FONT;
+use consts::{BIG_FONT_BASE_ADDRESS, FONT_BASE_ADDRESS};
+use font::{BIG_FONT, FONT};
 
+pub mod audio;
+pub mod config;
+pub mod conformance;
 pub mod consts;
+pub mod database;
 pub mod debugger;
 pub mod disassembler;
+pub mod display;
 pub mod emulator;
+pub mod error;
 pub mod font;
+pub mod frontend;
+pub mod instruction;
+pub mod keymap;
+pub mod movie;
+pub mod platform;
+pub mod quirks;
+pub mod rewind;
+pub mod savestate;
+pub mod screenshot;
+#[cfg(feature = "sdl")]
+pub mod sdl;
+pub mod trace;
+pub mod video;
 
 pub fn prep_buffer(buffer: &mut [u8]) {
     for i in (0..buffer.len() - 0x200).rev() {
@@ -16,6 +35,8 @@ pub fn prep_buffer(buffer: &mut [u8]) {
     }
     // copy font to 050-09F
     buffer[FONT_BASE_ADDRESS..FONT_BASE_ADDRESS + 80].copy_from_slice(&FONT);
+    // copy big font to 0A0-13F
+    buffer[BIG_FONT_BASE_ADDRESS..BIG_FONT_BASE_ADDRESS + 160].copy_from_slice(&BIG_FONT);
 }
 
 #[cfg(test)]
@@ -1,50 +1,556 @@
 use std::{
-    env,
-    fs::{self, File},
-    io::Read,
+    fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    process::ExitCode,
 };
 
-use chip8::{debugger, disassembler, emulator, prep_buffer};
+use clap::{Args, Parser, Subcommand};
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+use chip8::{
+    audio::{Waveform, WAVEFORMS},
+    config::{self, Config},
+    conformance, database, debugger, disassembler,
+    display::{self, Scaling, THEMES},
+    emulator,
+    instruction::{Syntax, SYNTAXES},
+    keymap::{self, Keymap, PRESETS as KEYMAPS},
+    movie::{self, Movie},
+    platform::{Platform, PLATFORMS},
+    prep_buffer,
+    quirks::{Quirks, PRESETS},
+    sdl::SdlFrontend,
+    trace::{self, TraceFilter, TraceFormat, TraceSettings},
+    video::VideoRecorder,
+};
+
+/// CHIP-8, SUPER-CHIP and XO-CHIP emulator, debugger and disassembler.
+#[derive(Parser)]
+#[command(name = "chip8", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a ROM in a window
+    Emu {
+        #[command(flatten)]
+        rom: RomArgs,
+        #[command(flatten)]
+        run: RunArgs,
+        #[command(flatten)]
+        emu: EmuArgs,
+    },
+    /// Step through a ROM in the debugger
+    Dbg {
+        #[command(flatten)]
+        rom: RomArgs,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// Print the disassembly of a ROM
+    Dis {
+        #[command(flatten)]
+        rom: RomArgs,
+    },
+    /// Run the bundled test ROMs headless and compare their final frames
+    Test {
+        /// Directory holding the test ROMs
+        #[arg(default_value = "roms")]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Args)]
+struct RomArgs {
+    /// Program file to load
+    rom: PathBuf,
+    /// Platform to emulate: chip8 (default), schip or xochip
+    #[arg(long, value_name = "PLATFORM", value_parser = parse_platform)]
+    platform: Option<Platform>,
+    /// Quirks preset instead of the platform's: vip, chip48, schip or xochip
+    #[arg(long, value_name = "PRESET", value_parser = parse_quirks)]
+    quirks: Option<Quirks>,
+    /// How instructions are written: description (default), classic or octo
+    #[arg(long, value_name = "SYNTAX", value_parser = parse_syntax)]
+    syntax: Option<Syntax>,
+}
+
+/// Options of the emulator and the debugger.
+#[derive(Args)]
+struct RunArgs {
+    /// Instructions per frame
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    ipf: Option<u32>,
+    /// Instructions per second, rounded to whole instructions per frame
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    ips: Option<u32>,
+    /// Seed for CXNN, for reproducible runs
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+    /// Settings file instead of ~/.config/chip8/config.toml
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Size of a pixel in screenshots
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    screenshot_scale: Option<u32>,
+    /// Size of a pixel in recorded videos
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    video_scale: Option<u32>,
+    #[command(flatten)]
+    display: DisplayArgs,
+    #[command(flatten)]
+    input: InputArgs,
+    #[command(flatten)]
+    sound: SoundArgs,
+    #[command(flatten)]
+    trace: TraceArgs,
+}
+
+#[derive(Args)]
+#[command(next_help_heading = "Display")]
+struct DisplayArgs {
+    /// Colour theme: classic, inverted, amber, green, gameboy or octo
+    #[arg(long, value_name = "THEME", value_parser = parse_theme)]
+    theme: Option<[[u8; 3]; 4]>,
+    /// Colour of lit pixels, as #RRGGBB
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    foreground: Option<[u8; 3]>,
+    /// Colour of dark pixels, as #RRGGBB
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    background: Option<[u8; 3]>,
+    /// Size of a pixel in the initial window, fractions are allowed
+    #[arg(long, value_name = "N", value_parser = parse_scale)]
+    scale: Option<f32>,
+    /// integer keeps whole pixel sizes, fit fills as much of the window as possible
+    #[arg(long, value_name = "MODE", value_parser = parse_scaling)]
+    scaling: Option<Scaling>,
+    /// Start in fullscreen, F11 toggles it
+    #[arg(long)]
+    fullscreen: bool,
+    /// Keep the window from being resized
+    #[arg(long)]
+    fixed_size: bool,
+    /// Draw lines between the pixels
+    #[arg(long)]
+    grid: bool,
+}
+
+#[derive(Args)]
+#[command(next_help_heading = "Input")]
+struct InputArgs {
+    /// Keyboard layout of the keypad: qwerty, qwertz, azerty or numpad
+    #[arg(long, value_name = "LAYOUT", value_parser = parse_keymap)]
+    keymap: Option<Keymap>,
+    /// Bind host keys to a keypad key, like 5=S,Space
+    #[arg(long, value_name = "KEY=HOSTKEYS", value_parser = parse_binding)]
+    bind: Vec<Binding>,
+    /// Stick deflection, out of 32767, before it presses the d-pad keys
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(0..32767))]
+    deadzone: Option<u16>,
+}
+
+#[derive(Args)]
+#[command(next_help_heading = "Sound")]
+struct SoundArgs {
+    /// Shape of the buzzer tone: square, triangle, sawtooth or sine
+    #[arg(long, value_name = "WAVEFORM", value_parser = parse_waveform)]
+    waveform: Option<Waveform>,
+    /// Frequency of the buzzer tone in Hz
+    #[arg(long, value_name = "HZ", value_parser = parse_frequency)]
+    frequency: Option<f32>,
+    /// Volume of the buzzer in percent
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u32).range(0..=100))]
+    volume: Option<u32>,
+}
+
+#[derive(Args)]
+#[command(next_help_heading = "Tracing")]
+struct TraceArgs {
+    /// Log every executed instruction to a file
+    #[arg(long, value_name = "FILE")]
+    trace: Option<PathBuf>,
+    /// Write the trace as text or binary records
+    #[arg(long, value_name = "FORMAT", value_parser = parse_trace_format, default_value = "text")]
+    trace_format: TraceFormat,
+    /// Only trace instructions between two hex addresses, like 200-2FF
+    #[arg(long, value_name = "START-END", value_parser = parse_range)]
+    trace_range: Option<RangeInclusive<u16>>,
+    /// Only trace instructions of these opcode classes, like 0,D,F
+    #[arg(long, value_name = "CLASSES", value_parser = parse_classes)]
+    trace_ops: Option<u16>,
+}
+
+/// Options only the emulator has.
+#[derive(Args)]
+struct EmuArgs {
+    /// Seconds of history kept for rewinding, 0 turns it off
+    #[arg(long, value_name = "SECONDS")]
+    rewind: Option<u32>,
+    /// Record the keypad input to a movie file
+    #[arg(long, value_name = "MOVIE")]
+    record: Option<PathBuf>,
+    /// Replay a movie with the settings it was recorded with
+    #[arg(long, value_name = "MOVIE")]
+    play: Option<PathBuf>,
+    /// Play the movie without a window and print the SHA-1 of the final frame
+    #[arg(long, requires = "play")]
+    headless: bool,
+    /// Exit with an error unless the SHA-1 of the final frame of a headless replay is this one
+    #[arg(long, value_name = "SHA1", requires = "headless")]
+    expect: Option<String>,
+    /// Record every frame to a .gif file or a directory of PPM images
+    #[arg(long, value_name = "PATH")]
+    record_video: Option<PathBuf>,
+}
+
+#[derive(Clone)]
+struct Binding {
+    key: u8,
+    hosts: Vec<String>,
+}
+
+fn expected(what: &str, name: &str, names: &[&str]) -> String {
+    format!(
+        "unknown {} '{}', expected one of: {}",
+        what,
+        name,
+        names.join(", ")
+    )
+}
+
+fn parse_platform(name: &str) -> Result<Platform, String> {
+    Platform::from_name(name).ok_or_else(|| expected("platform", name, &PLATFORMS))
+}
+
+fn parse_quirks(name: &str) -> Result<Quirks, String> {
+    Quirks::preset(name).ok_or_else(|| expected("quirks preset", name, &PRESETS))
+}
+
+fn parse_syntax(name: &str) -> Result<Syntax, String> {
+    Syntax::from_name(name).ok_or_else(|| expected("syntax", name, &SYNTAXES))
+}
+
+fn parse_theme(name: &str) -> Result<[[u8; 3]; 4], String> {
+    let names: Vec<_> = THEMES.iter().map(|(name, _)| *name).collect();
+    display::theme(name).ok_or_else(|| expected("theme", name, &names))
+}
+
+fn parse_color(color: &str) -> Result<[u8; 3], String> {
+    display::parse_color(color).ok_or_else(|| String::from("expected a colour like #FFB000"))
+}
+
+fn parse_scale(scale: &str) -> Result<f32, String> {
+    scale
+        .parse::<f32>()
+        .ok()
+        .filter(|&scale| scale >= 1.0)
+        .ok_or_else(|| String::from("expected a pixel size of at least 1"))
+}
+
+fn parse_scaling(name: &str) -> Result<Scaling, String> {
+    Scaling::from_name(name).ok_or_else(|| expected("scaling", name, &["integer", "fit"]))
+}
+
+fn parse_keymap(name: &str) -> Result<Keymap, String> {
+    Keymap::from_preset(name).ok_or_else(|| expected("keymap", name, &KEYMAPS))
+}
+
+fn parse_binding(binding: &str) -> Result<Binding, String> {
+    let (key, hosts) = keymap::parse_binding(binding)
+        .ok_or_else(|| String::from("expected a keypad key and host keys like 5=S,Space"))?;
+    if let Some((host, action)) = hosts
+        .iter()
+        .find_map(|host| Some((host, keymap::hotkey(host)?)))
+    {
+        return Err(format!("{} is the hotkey for {}", host, action));
+    }
+    Ok(Binding {
+        key,
+        hosts: hosts.into_iter().map(String::from).collect(),
+    })
+}
+
+fn parse_waveform(name: &str) -> Result<Waveform, String> {
+    Waveform::from_name(name).ok_or_else(|| expected("waveform", name, &WAVEFORMS))
+}
+
+fn parse_frequency(hz: &str) -> Result<f32, String> {
+    hz.parse::<f32>()
+        .ok()
+        .filter(|&hz| hz > 0.0)
+        .ok_or_else(|| String::from("expected a positive frequency in Hz"))
+}
+
+fn parse_trace_format(name: &str) -> Result<TraceFormat, String> {
+    match name {
+        "text" => Ok(TraceFormat::Text),
+        "binary" => Ok(TraceFormat::Binary),
+        _ => Err(expected("trace format", name, &["text", "binary"])),
+    }
+}
+
+fn parse_range(range: &str) -> Result<RangeInclusive<u16>, String> {
+    trace::parse_range(range)
+        .ok_or_else(|| String::from("expected a hex address range like 200-2FF"))
+}
+
+fn parse_classes(classes: &str) -> Result<u16, String> {
+    trace::parse_classes(classes).ok_or_else(|| String::from("expected opcode classes like 0,D,F"))
+}
+
+/// Config files of a run, missing ones are skipped.
+#[derive(Default)]
+struct ConfigFiles {
+    global: Option<PathBuf>,
+    rom: Option<PathBuf>,
+}
+
+impl RomArgs {
+    /// Settings from the config files and the ROM database, then --platform, --quirks and --syntax.
+    fn config(&self, program: &[u8], files: ConfigFiles) -> Result<Config, String> {
+        let profile = database::lookup(program).map_err(|err| err.to_string())?;
+        let mut config = Config::default();
+        config
+            .load_layers(
+                files.global.as_deref(),
+                profile.as_ref(),
+                files.rom.as_deref(),
+            )
+            .map_err(|err| err.to_string())?;
+        // quirks and speed of the database are meant for its platform
+        if let Some(platform) = self
+            .platform
+            .filter(|&platform| platform != config.platform)
+        {
+            config.set_platform(platform);
+        }
+        if let Some(quirks) = self.quirks {
+            config.quirks = quirks;
+        }
+        if let Some(syntax) = self.syntax {
+            config.syntax = syntax;
+        }
+        config.rom_path = Some(self.rom.clone());
+        Ok(config)
+    }
+}
+
+impl RunArgs {
+    /// The global config file, --config or the default one, and `<rom>.toml`.
+    fn config_files(&self, rom: &Path) -> ConfigFiles {
+        ConfigFiles {
+            global: self
+                .config
+                .clone()
+                .or_else(|| config::default_path().filter(|path| path.exists())),
+            rom: Some(config::rom_path(rom)).filter(|path| path.exists()),
+        }
+    }
+    /// Applies the command line, which takes precedence over the config files.
+    fn apply(&self, config: &mut Config) {
+        if let Some(ips) = self.ips {
+            config.set_instructions_per_second(ips);
+        }
+        if let Some(ipf) = self.ipf {
+            config.instructions_per_frame = ipf;
+        }
+        if let Some(seed) = self.seed {
+            config.seed = Some(seed);
+        }
+        if let Some(scale) = self.screenshot_scale {
+            config.screenshot_scale = scale;
+        }
+        if let Some(scale) = self.video_scale {
+            config.video_scale = scale;
+        }
+
+        let display = &self.display;
+        if let Some(palette) = display.theme {
+            config.palette = palette;
+        }
+        if let Some(color) = display.background {
+            config.palette[0] = color;
+        }
+        if let Some(color) = display.foreground {
+            config.palette[1] = color;
+        }
+        if let Some(scale) = display.scale {
+            config.display.scale = scale;
+        }
+        if let Some(scaling) = display.scaling {
+            config.display.scaling = scaling;
+        }
+        config.display.fullscreen |= display.fullscreen;
+        config.display.resizable &= !display.fixed_size;
+        config.display.grid |= display.grid;
+
+        if let Some(keymap) = &self.input.keymap {
+            config.keymap = keymap.clone();
+        }
+        for binding in &self.input.bind {
+            let hosts: Vec<_> = binding.hosts.iter().map(String::as_str).collect();
+            config.keymap.bind(binding.key, &hosts);
+        }
+        if let Some(deadzone) = self.input.deadzone {
+            config.stick_deadzone = deadzone;
+        }
+
+        if let Some(waveform) = self.sound.waveform {
+            config.audio.waveform = waveform;
+        }
+        if let Some(hz) = self.sound.frequency {
+            config.audio.frequency = hz;
+        }
+        if let Some(percent) = self.sound.volume {
+            config.audio.volume = percent as f32 / 100.0;
+        }
+
+        config.trace = self.trace.trace.clone().map(|path| TraceSettings {
+            path,
+            format: self.trace.trace_format,
+            filter: TraceFilter {
+                addresses: self.trace.trace_range.clone(),
+                classes: self.trace.trace_ops,
+            },
+        });
+    }
+}
 
-    match args.len() {
-        3 => {
-            let which = &args[1];
+fn read_rom(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|err| format!("could not read {}: {}", path.display(), err))
+}
 
-            let what = &args[2];
+/// Copies the program into a buffer the size of the platform's memory, with the fonts in place.
+fn load_rom(path: &Path, program: &[u8], platform: Platform) -> Result<Vec<u8>, String> {
+    let memory_size = platform.memory_size();
+    // the first 512 bytes of memory are reserved for the interpreter
+    if program.len() > memory_size - 0x200 {
+        return Err(format!(
+            "{} is too large: {} bytes, at most {} fit into memory",
+            path.display(),
+            program.len(),
+            memory_size - 0x200
+        ));
+    }
+    let mut buffer = program.to_vec();
+    buffer.resize(memory_size, 0);
+    prep_buffer(&mut buffer);
+    Ok(buffer)
+}
+
+/// The ROM's path, and its title if the database knows it.
+fn describe(path: &Path, config: &Config) -> String {
+    match &config.title {
+        Some(title) => format!("{} ({})", path.display(), title),
+        None => path.display().to_string(),
+    }
+}
 
-            let file = File::open(what).expect("Could not open file");
-            let metadata = fs::metadata(what).expect("Could not read metadata");
-            if metadata.len() > (4096 - 0x200) {
-                // Program memory is 4096 bytes, but the first 512 bytes are reserved for the interpreter
-                panic!("Program File too large");
+fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Test { dir } => {
+            let results = conformance::run_all(&dir);
+            if !conformance::print_results(&results) {
+                return Err(String::from(
+                    "some test ROMs did not match their golden frames",
+                ));
             }
-            let mut buffer = vec![0; metadata.len() as usize];
-            file.take(metadata.len())
-                .read_exact(&mut buffer)
-                .expect("buffer overflow");
-            buffer.resize(4096, 0);
-
-            prep_buffer(&mut buffer);
-
-            match which.as_str() {
-                "dis" => {
-                    println!("Disassembling: {}", what);
-                    disassembler::disassemble(&buffer);
-                }
-                "emu" => {
-                    println!("Emulating: {}", what);
-                    emulator::emulate(&buffer);
-                }
-                "dbg" => {
-                    println!("Debugging: {}", what);
-                    debugger::debug(&buffer);
+        }
+        Command::Dis { rom } => {
+            let program = read_rom(&rom.rom)?;
+            let config = rom.config(&program, ConfigFiles::default())?;
+            let buffer = load_rom(&rom.rom, &program, config.platform)?;
+            println!("Disassembling: {}", describe(&rom.rom, &config));
+            disassembler::disassemble(&buffer, &config);
+        }
+        Command::Dbg { rom, run } => {
+            let program = read_rom(&rom.rom)?;
+            let mut config = rom.config(&program, run.config_files(&rom.rom))?;
+            run.apply(&mut config);
+            let buffer = load_rom(&rom.rom, &program, config.platform)?;
+            println!("Debugging: {}", describe(&rom.rom, &config));
+            debugger::debug(&buffer, &config, &mut SdlFrontend::with_config(&config)?);
+        }
+        Command::Emu { rom, run, emu } => {
+            let program = read_rom(&rom.rom)?;
+            let mut config = rom.config(&program, run.config_files(&rom.rom))?;
+            run.apply(&mut config);
+            if let Some(seconds) = emu.rewind {
+                config.rewind_seconds = seconds;
+            }
+            let play = match &emu.play {
+                Some(path) => Some(Movie::load_file(path).map_err(|err| err.to_string())?),
+                None => None,
+            };
+            if let Some(movie) = &play {
+                // replay with the exact settings of the recording
+                movie.apply(&mut config);
+            }
+            config.record = emu.record;
+            config.record_video = emu.record_video;
+
+            let buffer = load_rom(&rom.rom, &program, config.platform)?;
+            if let Some(movie) = &play {
+                if movie.rom_hash != movie::rom_hash(&buffer) {
+                    return Err(String::from("the movie was recorded with a different ROM"));
                 }
-                _ => println!("Unknown command"),
             }
+            config.play = play;
+
+            println!("Emulating: {}", describe(&rom.rom, &config));
+            if emu.headless {
+                return replay_headless(&buffer, &config, emu.expect.as_deref());
+            }
+            emulator::emulate(&buffer, &config, &mut SdlFrontend::with_config(&config)?);
+        }
+    }
+    Ok(())
+}
+
+/// Plays the movie of `config` without a window, recording video if requested, and compares the final frame with
+/// `expect`.
+fn replay_headless(buffer: &[u8], config: &Config, expect: Option<&str>) -> Result<(), String> {
+    let Some(movie) = &config.play else {
+        return Err(String::from("--headless needs a movie to --play"));
+    };
+    let mut video = config
+        .record_video
+        .as_ref()
+        .and_then(|path| VideoRecorder::from_config(path, config));
+    let result = movie::replay_with(buffer, movie, |emulator| {
+        if let Some(recorder) = video.as_mut() {
+            if let Err(err) = recorder.push(&emulator.graphics) {
+                println!("Stopped recording video: {}", err);
+                video = None;
+            }
+        }
+    });
+    if let Some(recorder) = video {
+        recorder.stop();
+    }
+    let emulator = result.map_err(|err| err.to_string())?;
+    println!("Played {} frames", movie.frames.len());
+    let digest = emulator.graphics.digest();
+    println!("Final frame: {}", digest);
+    match expect {
+        Some(expected) if !expected.eq_ignore_ascii_case(&digest) => Err(format!(
+            "the final frame {} does not match the expected {}",
+            digest, expected
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
         }
-        _ => println!("Too few or too many arguments"),
     }
 }
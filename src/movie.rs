@@ -0,0 +1,180 @@
+//! Recordings of the keypad for replaying a run exactly.
+//!
+//! A movie (`.c8m`) is a 48 byte header followed by one entry per frame, all numbers are little endian:
+//!
+//! | Offset | Size | Field                                                        |
+//! |--------|------|--------------------------------------------------------------|
+//! | 0      | 4    | magic `C8MV`                                                 |
+//! | 4      | 2    | format version, currently 1                                  |
+//! | 6      | 1    | platform: 0 chip8, 1 schip, 2 xochip                         |
+//! | 7      | 1    | quirk flags, see below                                       |
+//! | 8      | 1    | FX55/FX65 index increment: 0 none, 1 X, 2 X + 1              |
+//! | 9      | 3    | reserved, 0                                                  |
+//! | 12     | 4    | instructions per frame                                       |
+//! | 16     | 8    | seed of the random number generator                          |
+//! | 24     | 20   | SHA-1 of the memory image the ROM was loaded into            |
+//! | 44     | 4    | number of frames                                             |
+//! | 48     | 2n   | keys held during each frame as a bit mask, bit N is key N    |
+//!
+//! Quirk flags: bit 0 shift uses VY, bit 1 jump uses VX, bit 2 VF reset, bit 3 clip sprites,
+//! bit 4 display wait.
+
+use std::{fs, path::Path};
+
+use crate::{
+    config::Config,
+    emulator::{Emulator, KeyState},
+    error::{Chip8Error, MovieError},
+    platform::Platform,
+    quirks::{IndexIncrement, Quirks},
+};
+
+pub const MAGIC: [u8; 4] = *b"C8MV";
+pub const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 48;
+
+/// Keypad input of a run together with everything needed to repeat it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Movie {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub instructions_per_frame: u32,
+    pub seed: u64,
+    pub rom_hash: [u8; 20],
+    /// Held keys for every frame, see [`KeyState::mask`].
+    pub frames: Vec<u16>,
+}
+
+impl Movie {
+    /// Starts an empty recording of `emulator` running `program`.
+    pub fn new(emulator: &Emulator, program: &[u8]) -> Self {
+        Movie {
+            platform: emulator.platform,
+            quirks: emulator.quirks,
+            instructions_per_frame: emulator.instructions_per_frame,
+            seed: emulator.seed,
+            rom_hash: rom_hash(program),
+            frames: Vec::new(),
+        }
+    }
+    /// Overrides the settings of `config` with the ones the movie was recorded with.
+    pub fn apply(&self, config: &mut Config) {
+        config.platform = self.platform;
+        config.quirks = self.quirks;
+        config.instructions_per_frame = self.instructions_per_frame;
+        config.seed = Some(self.seed);
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HEADER_SIZE + 2 * self.frames.len());
+        data.extend_from_slice(&MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.push(self.platform.id());
+        let q = &self.quirks;
+        data.push(
+            q.shift_uses_vy as u8
+                | (q.jump_uses_vx as u8) << 1
+                | (q.vf_reset as u8) << 2
+                | (q.clip_sprites as u8) << 3
+                | (q.display_wait as u8) << 4,
+        );
+        data.push(match q.index_increment {
+            IndexIncrement::None => 0,
+            IndexIncrement::X => 1,
+            IndexIncrement::XPlusOne => 2,
+        });
+        data.extend_from_slice(&[0; 3]);
+        data.extend_from_slice(&self.instructions_per_frame.to_le_bytes());
+        data.extend_from_slice(&self.seed.to_le_bytes());
+        data.extend_from_slice(&self.rom_hash);
+        data.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for keys in &self.frames {
+            data.extend_from_slice(&keys.to_le_bytes());
+        }
+        data
+    }
+    pub fn from_bytes(data: &[u8]) -> Result<Self, MovieError> {
+        if data.len() < HEADER_SIZE {
+            return Err(MovieError::Truncated);
+        }
+        if data[0..4] != MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let platform = Platform::from_id(data[6]).ok_or(MovieError::InvalidHeader)?;
+        let flags = data[7];
+        let index_increment = match data[8] {
+            0 => IndexIncrement::None,
+            1 => IndexIncrement::X,
+            2 => IndexIncrement::XPlusOne,
+            _ => return Err(MovieError::InvalidHeader),
+        };
+        let quirks = Quirks {
+            shift_uses_vy: flags & 1 != 0,
+            jump_uses_vx: flags & 1 << 1 != 0,
+            vf_reset: flags & 1 << 2 != 0,
+            clip_sprites: flags & 1 << 3 != 0,
+            display_wait: flags & 1 << 4 != 0,
+            index_increment,
+        };
+        let instructions_per_frame = u32::from_le_bytes(data[12..16].try_into().unwrap());
+        let seed = u64::from_le_bytes(data[16..24].try_into().unwrap());
+        let rom_hash = data[24..44].try_into().unwrap();
+        let count = u32::from_le_bytes(data[44..48].try_into().unwrap()) as usize;
+        let frames = data[HEADER_SIZE..]
+            .chunks_exact(2)
+            .map(|keys| u16::from_le_bytes([keys[0], keys[1]]))
+            .collect::<Vec<_>>();
+        if frames.len() < count {
+            return Err(MovieError::Truncated);
+        }
+        Ok(Movie {
+            platform,
+            quirks,
+            instructions_per_frame,
+            seed,
+            rom_hash,
+            frames: frames[..count].to_vec(),
+        })
+    }
+    pub fn save_file(&self, path: &Path) -> Result<(), MovieError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+    pub fn load_file(path: &Path) -> Result<Self, MovieError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+/// SHA-1 of the memory image, used to check a movie is played back on the ROM it was recorded with.
+pub fn rom_hash(program: &[u8]) -> [u8; 20] {
+    sha1_smol::Sha1::from(program).digest().bytes()
+}
+
+/// Plays the movie from the start without a frontend and returns the emulator after its last frame.
+pub fn replay(program: &[u8], movie: &Movie) -> Result<Emulator, Chip8Error> {
+    replay_with(program, movie, |_| {})
+}
+
+/// Like [`replay`], calling `on_frame` with the emulator after every frame.
+pub fn replay_with(
+    program: &[u8],
+    movie: &Movie,
+    mut on_frame: impl FnMut(&Emulator),
+) -> Result<Emulator, Chip8Error> {
+    let mut config = Config::new(movie.platform);
+    movie.apply(&mut config);
+    let mut emulator = Emulator::with_config(&config);
+    emulator.load(program);
+    for &keys in &movie.frames {
+        if emulator.exited {
+            break;
+        }
+        emulator.key_buffer = KeyState::from_mask(keys);
+        emulator.run_frame()?;
+        on_frame(&emulator);
+    }
+    Ok(emulator)
+}
@@ -0,0 +1,69 @@
+use crate::quirks::Quirks;
+
+/// The CHIP-8 dialect a ROM is written for.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Platform {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+pub const PLATFORMS: [&str; 3] = ["chip8", "schip", "xochip"];
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "chip8" | "chip-8" => Some(Platform::Chip8),
+            "schip" | "superchip" | "super-chip" => Some(Platform::SuperChip),
+            "xochip" | "xo-chip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+    /// Number identifying the platform in save states and movies.
+    pub fn id(&self) -> u8 {
+        match self {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2,
+        }
+    }
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Platform::Chip8),
+            1 => Some(Platform::SuperChip),
+            2 => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+    pub fn default_quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::vip(),
+            Platform::SuperChip => Quirks::schip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+    /// Instructions per 60 Hz frame most ROMs for the platform expect.
+    pub fn default_instructions_per_frame(&self) -> u32 {
+        match self {
+            Platform::Chip8 => 15,
+            Platform::SuperChip => 30,
+            Platform::XoChip => 100,
+        }
+    }
+    /// Size of the address space in bytes.
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::XoChip => 0x10000,
+            _ => 0x1000,
+        }
+    }
+    /// Whether the SUPER-CHIP 1.1 instructions (scrolling, hi-res, big font, RPL flags) are available.
+    pub fn has_superchip(&self) -> bool {
+        matches!(self, Platform::SuperChip | Platform::XoChip)
+    }
+    /// Whether the XO-CHIP extensions (long index loads, bitplanes, audio patterns) are available.
+    pub fn has_xochip(&self) -> bool {
+        matches!(self, Platform::XoChip)
+    }
+}
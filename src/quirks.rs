@@ -0,0 +1,98 @@
+/// How FX55 and FX65 change the index register after copying V0 to VX.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IndexIncrement {
+    /// I is left untouched (SUPER-CHIP).
+    None,
+    /// I is incremented by X (CHIP-48).
+    X,
+    /// I is incremented by X + 1 (COSMAC VIP, XO-CHIP).
+    XPlusOne,
+}
+
+/// Behaviours of ambiguous instructions that differ between CHIP-8 interpreters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Quirks {
+    /// 8XY6/8XYE copy VY into VX before shifting.
+    pub shift_uses_vy: bool,
+    /// BNNN is read as BXNN and jumps to XNN + VX instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    /// FX55/FX65 index register behaviour.
+    pub index_increment: IndexIncrement,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub vf_reset: bool,
+    /// Sprites are clipped at the screen edges instead of wrapping around.
+    pub clip_sprites: bool,
+    /// DXYN waits for the next vertical blank before drawing.
+    pub display_wait: bool,
+}
+
+pub const PRESETS: [&str; 4] = ["vip", "chip48", "schip", "xochip"];
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub const fn vip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            jump_uses_vx: false,
+            index_increment: IndexIncrement::XPlusOne,
+            vf_reset: true,
+            clip_sprites: true,
+            display_wait: true,
+        }
+    }
+    /// CHIP-48 on the HP48 calculators.
+    pub const fn chip48() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            jump_uses_vx: true,
+            index_increment: IndexIncrement::X,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+    /// SUPER-CHIP 1.1
+    pub const fn schip() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            jump_uses_vx: true,
+            index_increment: IndexIncrement::None,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+    /// XO-CHIP as implemented by Octo.
+    pub const fn xochip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            jump_uses_vx: false,
+            index_increment: IndexIncrement::XPlusOne,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+        }
+    }
+    /// Looks up a preset by name, see [`PRESETS`].
+    pub fn preset(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "vip" | "cosmac" | "chip8" => Some(Self::vip()),
+            "chip48" | "chip-48" => Some(Self::chip48()),
+            "schip" | "superchip" | "super-chip" => Some(Self::schip()),
+            "xochip" | "xo-chip" => Some(Self::xochip()),
+            _ => None,
+        }
+    }
+}
+
+/// The COSMAC VIP preset without `display_wait`, so programs driven by [`crate::emulator::Emulator::run`] alone
+/// do not wait for a timer tick at every sprite. [`Quirks::vip`] and the platform defaults of the command line
+/// keep the wait.
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            display_wait: false,
+            ..Self::vip()
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! History of recent frames for running a game backwards.
+//!
+//! Every frame is captured as a save state. Only the newest one is kept in full, older frames are
+//! stored as the XOR against their successor, run-length encoded, so frames that barely change
+//! take a few bytes each.
+
+use std::collections::VecDeque;
+
+use crate::{emulator::Emulator, savestate};
+
+/// Bounded ring buffer of past frames.
+pub struct Rewind {
+    /// Maximum number of frames that can be stepped back.
+    pub capacity: usize,
+    latest: Vec<u8>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    /// Keeps the last `seconds` of 60 Hz frames, 0 disables recording.
+    pub fn new(seconds: u32) -> Self {
+        Rewind {
+            capacity: seconds as usize * 60,
+            latest: Vec::new(),
+            deltas: VecDeque::new(),
+        }
+    }
+    /// Number of frames that can be stepped back.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+    /// Bytes held by the snapshots.
+    pub fn memory_usage(&self) -> usize {
+        self.latest.len() + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+    /// Records the current frame, dropping the oldest one when full.
+    pub fn push(&mut self, emulator: &Emulator) {
+        if self.capacity == 0 {
+            return;
+        }
+        let state = savestate::save(emulator);
+        if !self.latest.is_empty() {
+            self.deltas.push_back(encode_delta(&state, &self.latest));
+            if self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.latest = state;
+    }
+    /// Restores the frame before the last recorded one, returns false when there is none left.
+    pub fn step_back(&mut self, emulator: &mut Emulator) -> bool {
+        let Some(delta) = self.deltas.pop_back() else {
+            return false;
+        };
+        self.latest = apply_delta(&self.latest, &delta);
+        savestate::load(emulator, &self.latest).expect("rewind snapshots should be valid");
+        true
+    }
+    pub fn clear(&mut self) {
+        self.latest.clear();
+        self.deltas.clear();
+    }
+}
+
+/// Encodes `target` relative to `base` as its length followed by runs of
+/// (unchanged bytes, changed bytes, XOR of the changed bytes).
+fn encode_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let byte = |data: &[u8], i: usize| data.get(i).copied().unwrap_or(0);
+    let mut out = Vec::new();
+    put_varint(&mut out, target.len());
+    let mut i = 0;
+    while i < target.len() {
+        let start = i;
+        while i < target.len() && byte(base, i) == target[i] {
+            i += 1;
+        }
+        let skip = i - start;
+        let start = i;
+        while i < target.len() && byte(base, i) != target[i] {
+            i += 1;
+        }
+        put_varint(&mut out, skip);
+        put_varint(&mut out, i - start);
+        out.extend((start..i).map(|j| byte(base, j) ^ target[j]));
+    }
+    out
+}
+
+fn apply_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = get_varint(delta, &mut pos);
+    let mut out = base.to_vec();
+    out.resize(len, 0);
+    let mut i = 0;
+    while pos < delta.len() {
+        i += get_varint(delta, &mut pos);
+        let changed = get_varint(delta, &mut pos);
+        for (byte, x) in out[i..i + changed].iter_mut().zip(&delta[pos..]) {
+            *byte ^= x;
+        }
+        i += changed;
+        pos += changed;
+    }
+    out
+}
+
+/// LEB128, 7 bits per byte with the high bit set on all but the last byte.
+fn put_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn get_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
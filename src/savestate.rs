@@ -0,0 +1,237 @@
+//! Snapshots of the whole machine.
+//!
+//! A save state is a 16 byte header followed by the payload, all numbers are little endian:
+//!
+//! | Offset | Size | Field                                      |
+//! |--------|------|--------------------------------------------|
+//! | 0      | 4    | magic `C8ST`                               |
+//! | 4      | 2    | format version, currently 1                |
+//! | 6      | 1    | platform: 0 chip8, 1 schip, 2 xochip       |
+//! | 7      | 1    | reserved, 0                                |
+//! | 8      | 4    | payload length in bytes                    |
+//! | 12     | 4    | CRC-32 (IEEE) of the payload               |
+//!
+//! The payload of version 1 holds, in order:
+//! memory length (u32) and memory, hires flag (u8), selected planes (u8), the 128x64 frame buffer,
+//! V0-VF, I (u16), PC (u32), address of the last instruction (u32), stack depth (u8) and entries (u16),
+//! delay and sound timer (u16 each), held keys as a bit mask (u16), the key FX0A waits on (u8, 0xFF if none),
+//! RPL flags (16 bytes), whether F002 loaded an audio pattern (u8) and the pattern (16 bytes), pitch (u8), exited and vblank flags (u8 each),
+//! instructions into the current frame (u32), instructions executed since loading (u64), seed (u64) and the two words of the RNG state (u64 each).
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use oorandom::Rand32;
+
+use crate::{
+    consts::STACK_SIZE,
+    emulator::{Emulator, KeyState},
+    error::StateError,
+};
+
+pub const MAGIC: [u8; 4] = *b"C8ST";
+pub const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 16;
+/// Number of slots reachable with the save state hotkeys.
+pub const SLOTS: u8 = 10;
+
+/// Serializes the emulator into a save state.
+pub fn save(emulator: &Emulator) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(emulator.memory.len() + 8 * 1024 + 128);
+    let put_u16 = |out: &mut Vec<u8>, v: u16| out.extend_from_slice(&v.to_le_bytes());
+    let put_u32 = |out: &mut Vec<u8>, v: u32| out.extend_from_slice(&v.to_le_bytes());
+    let put_u64 = |out: &mut Vec<u8>, v: u64| out.extend_from_slice(&v.to_le_bytes());
+
+    put_u32(&mut payload, emulator.memory.len() as u32);
+    payload.extend_from_slice(&emulator.memory);
+    payload.push(emulator.graphics.hires as u8);
+    payload.push(emulator.graphics.planes);
+    payload.extend_from_slice(&emulator.graphics.buffer);
+    payload.extend(emulator.registers.iter().map(|r| r.v));
+    put_u16(&mut payload, emulator.index);
+    put_u32(&mut payload, emulator.pc as u32);
+    put_u32(&mut payload, emulator.instruction_address as u32);
+    payload.push(emulator.stack.len() as u8);
+    for &address in &emulator.stack {
+        put_u16(&mut payload, address);
+    }
+    put_u16(&mut payload, emulator.delay_timer);
+    put_u16(&mut payload, emulator.sound_timer);
+    put_u16(&mut payload, emulator.key_buffer.mask());
+    payload.push(emulator.waiting_key.unwrap_or(0xFF));
+    payload.extend_from_slice(&emulator.rpl);
+    payload.push(emulator.audio_pattern.is_some() as u8);
+    payload.extend_from_slice(&emulator.audio_pattern.unwrap_or_default());
+    payload.push(emulator.pitch);
+    payload.push(emulator.exited as u8);
+    payload.push(emulator.vblank as u8);
+    put_u32(&mut payload, emulator.frame_cycle);
+    put_u64(&mut payload, emulator.cycles);
+    put_u64(&mut payload, emulator.seed);
+    let (state, increment) = emulator.rng.state();
+    put_u64(&mut payload, state);
+    put_u64(&mut payload, increment);
+
+    let mut data = Vec::with_capacity(HEADER_SIZE + payload.len());
+    data.extend_from_slice(&MAGIC);
+    put_u16(&mut data, VERSION);
+    data.push(emulator.platform.id());
+    data.push(0);
+    put_u32(&mut data, payload.len() as u32);
+    put_u32(&mut data, crc32(&payload));
+    data.extend_from_slice(&payload);
+    data
+}
+
+/// Restores a save state made by [`save`], the emulator is left untouched if it is invalid.
+pub fn load(emulator: &mut Emulator, data: &[u8]) -> Result<(), StateError> {
+    let mut header = Reader::new(data);
+    if header.bytes(4)? != MAGIC {
+        return Err(StateError::BadMagic);
+    }
+    let version = header.u16()?;
+    if version != VERSION {
+        return Err(StateError::UnsupportedVersion(version));
+    }
+    if header.u8()? != emulator.platform.id() {
+        return Err(StateError::PlatformMismatch);
+    }
+    header.u8()?;
+    let length = header.u32()? as usize;
+    let checksum = header.u32()?;
+    let payload = header.bytes(length)?;
+    if crc32(payload) != checksum {
+        return Err(StateError::ChecksumMismatch);
+    }
+
+    let mut r = Reader::new(payload);
+    let memory_size = r.u32()? as usize;
+    if memory_size != emulator.memory.len() {
+        return Err(StateError::PlatformMismatch);
+    }
+    let memory = r.bytes(memory_size)?;
+    let hires = r.u8()? != 0;
+    let planes = r.u8()?;
+    if planes > 3 {
+        return Err(StateError::Invalid("selected planes"));
+    }
+    let buffer = r.bytes(emulator.graphics.buffer.len())?;
+    let registers = r.bytes(16)?;
+    let index = r.u16()?;
+    let pc = r.u32()? as usize;
+    let instruction_address = r.u32()? as usize;
+    let depth = r.u8()?;
+    if depth as usize > STACK_SIZE {
+        return Err(StateError::Invalid("stack depth"));
+    }
+    let stack = (0..depth).map(|_| r.u16()).collect::<Result<Vec<_>, _>>()?;
+    let delay_timer = r.u16()?;
+    let sound_timer = r.u16()?;
+    let keys = r.u16()?;
+    let waiting_key = r.u8()?;
+    let rpl = r.bytes(16)?;
+    let has_pattern = r.u8()? != 0;
+    let audio_pattern = r.bytes(16)?;
+    let pitch = r.u8()?;
+    let exited = r.u8()? != 0;
+    let vblank = r.u8()? != 0;
+    let frame_cycle = r.u32()?;
+    let cycles = r.u64()?;
+    let seed = r.u64()?;
+    let rng = (r.u64()?, r.u64()?);
+
+    // everything was read, only now touch the emulator
+    emulator.memory.copy_from_slice(memory);
+    emulator.graphics.hires = hires;
+    emulator.graphics.planes = planes;
+    emulator.graphics.buffer.copy_from_slice(buffer);
+    emulator.graphics.dirty = true;
+    for (register, &v) in emulator.registers.iter_mut().zip(registers) {
+        register.v = v;
+    }
+    emulator.index = index;
+    emulator.pc = pc;
+    emulator.instruction_address = instruction_address;
+    emulator.stack = stack;
+    emulator.delay_timer = delay_timer;
+    emulator.sound_timer = sound_timer;
+    emulator.key_buffer = KeyState::from_mask(keys);
+    emulator.waiting_key = (waiting_key != 0xFF).then_some(waiting_key);
+    emulator.rpl.copy_from_slice(rpl);
+    emulator.audio_pattern = has_pattern.then(|| audio_pattern.try_into().unwrap());
+    emulator.pitch = pitch;
+    emulator.exited = exited;
+    emulator.vblank = vblank;
+    emulator.frame_cycle = frame_cycle;
+    emulator.cycles = cycles;
+    emulator.seed = seed;
+    emulator.rng = Rand32::from_state(rng);
+    Ok(())
+}
+
+/// File of a numbered slot, stored next to the ROM as `<rom>.state<slot>`.
+pub fn slot_path(rom: &Path, slot: u8) -> PathBuf {
+    let mut path = rom.as_os_str().to_owned();
+    path.push(format!(".state{}", slot));
+    PathBuf::from(path)
+}
+
+pub fn save_file(emulator: &Emulator, path: &Path) -> Result<(), StateError> {
+    fs::write(path, save(emulator))?;
+    Ok(())
+}
+
+pub fn load_file(emulator: &mut Emulator, path: &Path) -> Result<(), StateError> {
+    let data = fs::read(path)?;
+    load(emulator, &data)
+}
+
+/// CRC-32 with the IEEE polynomial, as used by zip and png.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Little endian cursor over a byte slice.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or(StateError::Truncated)?;
+        self.pos += n;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+    fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
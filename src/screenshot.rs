@@ -0,0 +1,117 @@
+//! Exports the framebuffer as an image.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::emulator::Graphics;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    /// Monochrome netpbm, lit pixels are white like on screen.
+    Pbm,
+    /// Colour netpbm.
+    Ppm,
+}
+
+impl ImageFormat {
+    /// Picks the format from the file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "pbm" => Some(ImageFormat::Pbm),
+            "ppm" => Some(ImageFormat::Ppm),
+            _ => None,
+        }
+    }
+}
+
+/// Renders the framebuffer as RGB rows, every pixel `scale` times as large, coloured with `palette`.
+pub fn render(graphics: &Graphics, palette: &[[u8; 3]; 4], scale: u32) -> (u32, u32, Vec<u8>) {
+    let scale = scale.max(1) as usize;
+    let (width, height) = (graphics.width() * scale, graphics.height() * scale);
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let color = graphics.color(x / scale, y / scale) as usize & 0x3;
+            rgb.extend_from_slice(&palette[color]);
+        }
+    }
+    (width as u32, height as u32, rgb)
+}
+
+/// Writes the framebuffer to `path` in the format of its extension.
+pub fn save(
+    graphics: &Graphics,
+    path: &Path,
+    palette: &[[u8; 3]; 4],
+    scale: u32,
+) -> io::Result<()> {
+    let format = ImageFormat::from_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "screenshots need a .png, .pbm or .ppm extension",
+        )
+    })?;
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(graphics, &mut writer, format, palette, scale)?;
+    writer.flush()
+}
+
+pub fn write(
+    graphics: &Graphics,
+    writer: &mut dyn Write,
+    format: ImageFormat,
+    palette: &[[u8; 3]; 4],
+    scale: u32,
+) -> io::Result<()> {
+    match format {
+        ImageFormat::Png => {
+            let (width, height, rgb) = render(graphics, palette, scale);
+            let mut encoder = png::Encoder::new(writer, width, height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder
+                .write_header()
+                .and_then(|mut png| png.write_image_data(&rgb))
+                .map_err(io::Error::other)
+        }
+        ImageFormat::Ppm => {
+            let (width, height, rgb) = render(graphics, palette, scale);
+            write!(writer, "P6\n{} {}\n255\n", width, height)?;
+            writer.write_all(&rgb)
+        }
+        ImageFormat::Pbm => {
+            let scale = scale.max(1) as usize;
+            let (width, height) = (graphics.width() * scale, graphics.height() * scale);
+            write!(writer, "P4\n{} {}\n", width, height)?;
+            for y in 0..height {
+                // rows are padded to whole bytes, a set bit is black
+                let mut row = vec![0; width.div_ceil(8)];
+                for x in 0..width {
+                    if !graphics.pixel(x / scale, y / scale) {
+                        row[x / 8] |= 0x80 >> (x % 8);
+                    }
+                }
+                writer.write_all(&row)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// First unused `<rom>.shot<n>.png` next to the ROM.
+pub fn next_path(rom: &Path) -> PathBuf {
+    (0..)
+        .map(|n| {
+            let mut path = rom.as_os_str().to_owned();
+            path.push(format!(".shot{}.png", n));
+            PathBuf::from(path)
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
@@ -0,0 +1,497 @@
+use sdl2::{
+    audio::{AudioCallback, AudioDevice, AudioSpecDesired},
+    controller::{Axis, Button, GameController},
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{Canvas, Texture},
+    video::{FullscreenType, Window},
+    EventPump, GameControllerSubsystem,
+};
+
+use crate::{
+    audio::{AudioSettings, Beeper, Sound},
+    config::Config,
+    consts::{AUDIO_SAMPLE_RATE, PALETTE},
+    display::{self, DisplaySettings},
+    emulator::{Graphics, KeyState},
+    frontend::{AudioSink, Hotkey, InputSource, VideoSink},
+    keymap::{HeldInputs, Keymap, Source},
+};
+
+/// Resolves the host key names of `keymap` to SDL keycodes, unknown names are reported and skipped.
+pub fn keycodes(keymap: &Keymap) -> Vec<(Keycode, u8)> {
+    keymap
+        .bindings
+        .iter()
+        .filter_map(|(name, key)| match Keycode::from_name(name) {
+            Some(keycode) => Some((keycode, *key)),
+            None => {
+                println!("Unknown key '{}' bound to CHIP-8 key {:X}", name, key);
+                None
+            }
+        })
+        .collect()
+}
+
+pub fn find_sdl_gl_driver() -> Option<u32> {
+    for (index, item) in sdl2::render::drivers().enumerate() {
+        if item.name == "opengl" {
+            return Some(index as u32);
+        }
+    }
+    None
+}
+
+pub struct Display {
+    pub canvas: Canvas<Window>,
+    /// Colours for the four combinations of the two XO-CHIP bitplanes.
+    pub palette: [Color; 4],
+    pub settings: DisplaySettings,
+    grid_color: Color,
+    /// Streaming texture holding the framebuffer at its native resolution, and its width.
+    texture: Option<(Texture, usize)>,
+    /// Set when the window has to be drawn again although the framebuffer did not change.
+    needs_redraw: bool,
+}
+
+impl Display {
+    pub fn new(context: &sdl2::Sdl) -> Result<Self, String> {
+        Self::with_settings(context, &PALETTE, DisplaySettings::default())
+    }
+    pub fn with_palette(context: &sdl2::Sdl, palette: &[[u8; 3]; 4]) -> Result<Self, String> {
+        Self::with_settings(context, palette, DisplaySettings::default())
+    }
+    /// Opens the window, fails if SDL has no video or OpenGL renderer.
+    pub fn with_settings(
+        context: &sdl2::Sdl,
+        palette: &[[u8; 3]; 4],
+        settings: DisplaySettings,
+    ) -> Result<Self, String> {
+        let video_subsystem = context
+            .video()
+            .map_err(|err| format!("could not initialize SDL video: {}", err))?;
+        let (width, height) = settings.window_size();
+        let mut window = video_subsystem.window("CHIP 8", width, height);
+        window.opengl().position_centered();
+        if settings.resizable {
+            window.resizable();
+        }
+        if settings.fullscreen {
+            window.fullscreen_desktop();
+        }
+        let driver = find_sdl_gl_driver().ok_or("SDL has no OpenGL render driver")?;
+        let canvas = window
+            .build()
+            .map_err(|err| format!("could not open the window: {}", err))?
+            .into_canvas()
+            .index(driver)
+            .build()
+            .map_err(|err| format!("could not create the renderer: {}", err))?;
+        let [r, g, b] = display::grid_color(palette);
+        Ok(Display {
+            canvas,
+            palette: palette.map(|[r, g, b]| Color::RGB(r, g, b)),
+            settings,
+            grid_color: Color::RGB(r, g, b),
+            texture: None,
+            needs_redraw: true,
+        })
+    }
+    /// Names the program in the window title.
+    pub fn set_title(&mut self, title: &str) {
+        let title = format!("{} - CHIP 8", title);
+        if let Err(err) = self.canvas.window_mut().set_title(&title) {
+            println!("Could not set the window title: {}", err);
+        }
+    }
+    /// Switches between a window and the full desktop.
+    pub fn toggle_fullscreen(&mut self) {
+        self.settings.fullscreen = !self.settings.fullscreen;
+        let state = if self.settings.fullscreen {
+            FullscreenType::Desktop
+        } else {
+            FullscreenType::Off
+        };
+        if let Err(err) = self.canvas.window_mut().set_fullscreen(state) {
+            println!("Could not toggle fullscreen: {}", err);
+        }
+        self.needs_redraw = true;
+    }
+    /// Uploads the framebuffer if it changed and shows it, called once per frame.
+    pub fn draw(&mut self, graphics: &Graphics) -> Result<(), String> {
+        let (width, height) = (graphics.width(), graphics.height());
+        let mut upload = graphics.is_dirty();
+        if self.texture.as_ref().map(|(_, w)| *w) != Some(width) {
+            // the resolution changed, SUPER-CHIP programs do this rarely
+            let texture = self
+                .canvas
+                .texture_creator()
+                .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+                .map_err(|err| err.to_string())?;
+            if let Some((old, _)) = self.texture.replace((texture, width)) {
+                // SAFETY: the old texture was created by this canvas, which is still alive
+                unsafe { old.destroy() };
+            }
+            upload = true;
+        }
+        let Some((texture, _)) = self.texture.as_mut() else {
+            return Ok(());
+        };
+        if upload {
+            let palette = self.palette;
+            texture.with_lock(None, |pixels, pitch| {
+                for y in 0..height {
+                    let row = &mut pixels[y * pitch..y * pitch + width * 3];
+                    for (x, rgb) in row.chunks_exact_mut(3).enumerate() {
+                        let color = palette[graphics.color(x, y) as usize & 0x3];
+                        rgb.copy_from_slice(&[color.r, color.g, color.b]);
+                    }
+                }
+            })?;
+        } else if !self.needs_redraw {
+            return Ok(());
+        }
+        self.needs_redraw = false;
+
+        let output = self.canvas.output_size().unwrap_or((640, 320));
+        let (left, top, view_width, view_height) =
+            display::viewport(output, (width as u32, height as u32), self.settings.scaling);
+        self.canvas.set_draw_color(Color::BLACK);
+        self.canvas.clear();
+        self.canvas
+            .copy(texture, None, Rect::new(left, top, view_width, view_height))?;
+        if self.settings.grid && view_width as usize / width >= 3 {
+            // lines on the left and top edge of every pixel, rounded like the scaled texture
+            let edge = |i: usize, size: u32, count: usize| (i * size as usize / count) as i32;
+            self.canvas.set_draw_color(self.grid_color);
+            let (right, bottom) = (left + view_width as i32 - 1, top + view_height as i32 - 1);
+            for x in 1..width {
+                let x = left + edge(x, view_width, width);
+                self.canvas.draw_line((x, top), (x, bottom))?;
+            }
+            for y in 1..height {
+                let y = top + edge(y, view_height, height);
+                self.canvas.draw_line((left, y), (right, y))?;
+            }
+        }
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+impl AudioCallback for Beeper {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.fill(out);
+    }
+}
+
+/// Opens the default playback device, the emulator stays silent if there is none.
+pub fn open_audio(context: &sdl2::Sdl, settings: AudioSettings) -> Option<AudioDevice<Beeper>> {
+    let desired = AudioSpecDesired {
+        freq: Some(AUDIO_SAMPLE_RATE),
+        channels: Some(1),
+        samples: None,
+    };
+    let device = context.audio().and_then(|audio| {
+        audio.open_playback(None, &desired, |spec| {
+            Beeper::new(settings, spec.freq as u32)
+        })
+    });
+    match device {
+        Ok(device) => {
+            device.resume();
+            Some(device)
+        }
+        Err(err) => {
+            println!("Could not open audio device: {}", err);
+            None
+        }
+    }
+}
+
+/// Resolves the button names of `keymap` to SDL controller buttons, unknown names are reported and skipped.
+pub fn buttons(keymap: &Keymap) -> Vec<(Button, u8)> {
+    keymap
+        .bindings
+        .iter()
+        .filter_map(|(name, key)| match Button::from_string(name) {
+            Some(button) => Some((button, *key)),
+            None => {
+                println!("Unknown button '{}' bound to CHIP-8 key {:X}", name, key);
+                None
+            }
+        })
+        .collect()
+}
+
+/// A connected game controller and the d-pad direction its left stick points to.
+struct Pad {
+    controller: GameController,
+    stick: (i8, i8),
+}
+
+/// SDL2 window with keyboard and game controller input and a beeper.
+pub struct SdlFrontend {
+    pub display: Display,
+    pub event_pump: EventPump,
+    pub audio: Option<AudioDevice<Beeper>>,
+    /// Host keys and the CHIP-8 key they press.
+    pub keys: Vec<(Keycode, u8)>,
+    /// Host keys, buttons and stick directions held down, a CHIP-8 key is released when the last input bound to
+    /// it is.
+    pub held: HeldInputs,
+    /// Controller buttons and the CHIP-8 key they press.
+    pub buttons: Vec<(Button, u8)>,
+    /// Stick deflection below which the left stick counts as centered.
+    pub stick_deadzone: u16,
+    controllers: Option<GameControllerSubsystem>,
+    pads: Vec<Pad>,
+    _context: sdl2::Sdl,
+}
+
+impl SdlFrontend {
+    pub fn new() -> Result<Self, String> {
+        Self::with_config(&Config::default())
+    }
+    /// Uses the palette, display, key and controller bindings and audio settings of `config`, fails if SDL or the
+    /// window cannot be initialized.
+    pub fn with_config(config: &Config) -> Result<Self, String> {
+        let sdl_context =
+            sdl2::init().map_err(|err| format!("could not initialize SDL: {}", err))?;
+        let mut display = Display::with_settings(&sdl_context, &config.palette, config.display)?;
+        let audio = open_audio(&sdl_context, config.audio);
+        // controllers are opened as SDL reports them, including those connected at startup
+        let controllers = sdl_context
+            .game_controller()
+            .map_err(|err| println!("Could not initialize game controllers: {}", err))
+            .ok();
+        let event_pump = sdl_context
+            .event_pump()
+            .map_err(|err| format!("could not read SDL events: {}", err))?;
+        if let Some(title) = &config.title {
+            display.set_title(title);
+        }
+        display.canvas.present();
+        Ok(SdlFrontend {
+            display,
+            event_pump,
+            audio,
+            keys: keycodes(&config.keymap),
+            held: HeldInputs::new(),
+            buttons: buttons(&config.controller),
+            stick_deadzone: config.stick_deadzone,
+            controllers,
+            pads: Vec::new(),
+            _context: sdl_context,
+        })
+    }
+}
+
+impl SdlFrontend {
+    fn connect(&mut self, index: u32) {
+        let Some(controllers) = &self.controllers else {
+            return;
+        };
+        match controllers.open(index) {
+            Ok(controller) => {
+                println!("Connected controller {}", controller.name());
+                self.pads.push(Pad {
+                    controller,
+                    stick: (0, 0),
+                });
+            }
+            Err(err) => println!("Could not open controller: {}", err),
+        }
+    }
+    fn disconnect(&mut self, id: u32, keys: &mut KeyState) {
+        if let Some(index) = self
+            .pads
+            .iter()
+            .position(|pad| pad.controller.instance_id() == id)
+        {
+            let pad = self.pads.remove(index);
+            println!("Disconnected controller {}", pad.controller.name());
+            // nothing would release the keys it was holding, other devices keep theirs
+            self.held.disconnect(id, keys);
+        }
+    }
+    fn set_button(&mut self, source: Source, keys: &mut KeyState, button: Button, held: bool) {
+        for &(_, key) in self.buttons.iter().filter(|&&(b, _)| b == button) {
+            if held {
+                self.held.press(source, &button.string(), key, keys);
+            } else {
+                self.held.release(source, &button.string(), key, keys);
+            }
+        }
+    }
+    /// Turns the left stick into d-pad presses once it leaves the deadzone.
+    fn move_stick(&mut self, id: u32, axis: Axis, value: i16, keys: &mut KeyState) {
+        let deadzone = self.stick_deadzone as i32;
+        let direction = match value as i32 {
+            v if v > deadzone => 1,
+            v if v < -deadzone => -1,
+            _ => 0,
+        };
+        let Some(pad) = self
+            .pads
+            .iter_mut()
+            .find(|pad| pad.controller.instance_id() == id)
+        else {
+            return;
+        };
+        let (old, buttons) = match axis {
+            Axis::LeftX => (
+                std::mem::replace(&mut pad.stick.0, direction),
+                [Button::DPadLeft, Button::DPadRight],
+            ),
+            Axis::LeftY => (
+                std::mem::replace(&mut pad.stick.1, direction),
+                [Button::DPadUp, Button::DPadDown],
+            ),
+            _ => return,
+        };
+        if old == direction {
+            return;
+        }
+        if old != 0 {
+            self.set_button(Source::Stick(id), keys, buttons[(old > 0) as usize], false);
+        }
+        if direction != 0 {
+            self.set_button(
+                Source::Stick(id),
+                keys,
+                buttons[(direction > 0) as usize],
+                true,
+            );
+        }
+    }
+}
+
+impl VideoSink for SdlFrontend {
+    fn present(&mut self, graphics: &Graphics) {
+        if let Err(err) = self.display.draw(graphics) {
+            println!("Could not draw the frame: {}", err);
+        }
+    }
+}
+
+impl AudioSink for SdlFrontend {
+    fn set_playing(&mut self, sound: Option<Sound>) {
+        if let Some(audio) = self.audio.as_mut() {
+            audio.lock().play(sound);
+        }
+    }
+}
+
+impl InputSource for SdlFrontend {
+    fn poll(&mut self, keys: &mut KeyState) -> Vec<Hotkey> {
+        let mut hotkeys = Vec::new();
+        let events: Vec<_> = self.event_pump.poll_iter().collect();
+        for event in events {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => hotkeys.push(Hotkey::Quit),
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::Tab | Keycode::LShift | Keycode::Backspace)),
+                    repeat: false,
+                    ..
+                }
+                | Event::KeyUp {
+                    keycode: Some(keycode @ (Keycode::Tab | Keycode::LShift | Keycode::Backspace)),
+                    ..
+                } => {
+                    let held = matches!(event, Event::KeyDown { .. });
+                    hotkeys.push(match keycode {
+                        Keycode::Tab => Hotkey::FastForward(held),
+                        Keycode::LShift => Hotkey::SlowMotion(held),
+                        _ => Hotkey::Rewind(held),
+                    });
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    repeat: false,
+                    ..
+                } => hotkeys.push(Hotkey::SaveState),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    repeat: false,
+                    ..
+                } => hotkeys.push(Hotkey::NextSlot),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    repeat: false,
+                    ..
+                } => hotkeys.push(Hotkey::LoadState),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    repeat: false,
+                    ..
+                } => hotkeys.push(Hotkey::Screenshot),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    repeat: false,
+                    ..
+                } => hotkeys.push(Hotkey::ToggleVideo),
+                Event::Window {
+                    win_event:
+                        WindowEvent::Exposed | WindowEvent::Resized(..) | WindowEvent::SizeChanged(..),
+                    ..
+                } => self.display.needs_redraw = true,
+                Event::ControllerDeviceAdded { which, .. } => self.connect(which),
+                Event::ControllerDeviceRemoved { which, .. } => self.disconnect(which, keys),
+                Event::ControllerButtonDown { which, button, .. } => {
+                    self.set_button(Source::Buttons(which), keys, button, true)
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    self.set_button(Source::Buttons(which), keys, button, false)
+                }
+                Event::ControllerAxisMotion {
+                    which, axis, value, ..
+                } => self.move_stick(which, axis, value, keys),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    repeat: false,
+                    ..
+                } => self.display.toggle_fullscreen(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    repeat: false,
+                    ..
+                } => {
+                    // muting only concerns the host, the emulator keeps its sound timer
+                    if let Some(audio) = self.audio.as_mut() {
+                        let mut beeper = audio.lock();
+                        beeper.muted = !beeper.muted;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    for &(_, key) in self.keys.iter().filter(|&&(k, _)| k == keycode) {
+                        self.held
+                            .press(Source::Keyboard, &keycode.name(), key, keys);
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    for &(_, key) in self.keys.iter().filter(|&&(k, _)| k == keycode) {
+                        self.held
+                            .release(Source::Keyboard, &keycode.name(), key, keys);
+                    }
+                }
+                _ => {}
+            }
+        }
+        hotkeys
+    }
+}
@@ -1,8 +1,31 @@
-use super::emulator::Emulator;
+use super::audio::{AudioSettings, Beeper, Sound, Waveform};
+use super::config::Config;
+use super::consts::PALETTE;
+use super::database::{self, Database};
+use super::debugger::Debugger;
+use super::disassembler::Disassembler;
+use super::display::{self, Scaling};
+use super::emulator::{emulate, Emulator, KeyState};
+use super::error::{Chip8Error, ConfigError, DatabaseError, StateError};
+use super::frontend::Headless;
+use super::instruction::{Instruction, Syntax};
+use super::keymap::{self, HeldInputs, Keymap, Source};
+use super::movie::{self, Movie};
+use super::platform::Platform;
 use super::prep_buffer;
+use super::quirks::{IndexIncrement, Quirks};
+use super::rewind::Rewind;
+use super::savestate;
+use super::screenshot::{self, ImageFormat};
+use super::trace::{self, TraceFilter, TraceFormat, TraceSettings, Tracer};
+use super::video::VideoRecorder;
 
 fn create_buffer(program: Vec<u8>) -> Vec<u8> {
-    let mut buffer = vec![0u8; 4096];
+    create_buffer_sized(program, 4096)
+}
+
+fn create_buffer_sized(program: Vec<u8>, size: usize) -> Vec<u8> {
+    let mut buffer = vec![0u8; size];
     buffer[0..program.len()].copy_from_slice(&program);
     prep_buffer(&mut buffer);
     buffer
@@ -16,7 +39,7 @@ fn test_opcode_1_nnn() {
     emulator.load(&program);
 
     assert!(emulator.pc == 0x200, "PC should initially be 0x200");
-    emulator.run(None);
+    emulator.run().unwrap();
     assert!(
         emulator.pc == 0x234,
         "PC should be 0x234 after opcode 0x1234"
@@ -31,7 +54,7 @@ fn test_opcode_3_x_nn_pos() {
     emulator.load(&program);
 
     assert!(emulator.pc == 0x200, "PC should initially be 0x200");
-    emulator.run(None);
+    emulator.run().unwrap();
     assert!(
         emulator.pc == 0x204,
         "PC should be 0x204 after opcode 0x3000"
@@ -46,7 +69,7 @@ fn test_opcode_3_x_nn_neg() {
     emulator.load(&program);
 
     assert!(emulator.pc == 0x200, "PC should initially be 0x200");
-    emulator.run(None);
+    emulator.run().unwrap();
     assert!(
         emulator.pc == 0x202,
         "PC should be 0x202 after opcode 0x3001"
@@ -61,7 +84,7 @@ fn test_opcode_4_x_nn_pos() {
     emulator.load(&program);
 
     assert!(emulator.pc == 0x200, "PC should initially be 0x200");
-    emulator.run(None);
+    emulator.run().unwrap();
     assert!(
         emulator.pc == 0x204,
         "PC should be 0x204 after opcode 0x4001"
@@ -76,7 +99,7 @@ fn test_opcode_4_x_nn_neg() {
     emulator.load(&program);
 
     assert!(emulator.pc == 0x200, "PC should initially be 0x200");
-    emulator.run(None);
+    emulator.run().unwrap();
     assert!(
         emulator.pc == 0x202,
         "PC should be 0x202 after opcode 0x4000"
@@ -91,14 +114,14 @@ fn test_opcode_5_x_y_pos() {
     emulator.load(&program);
 
     assert!(emulator.pc == 0x200, "PC should initially be 0x200");
-    emulator.run(None);
+    emulator.run().unwrap();
     assert!(
         emulator.pc == 0x204,
         "PC should be 0x204 after opcode 0x5120"
     );
     emulator.registers[3].v = 0x4;
     emulator.registers[4].v = 0x4;
-    emulator.run(None);
+    emulator.run().unwrap();
     assert!(
         emulator.pc == 0x208,
         "PC should be 0x208 after opcode 0x5340"
@@ -114,14 +137,14 @@ fn test_opcode_5_x_y_neg() {
 
     assert!(emulator.pc == 0x200, "PC should initially be 0x200");
     emulator.registers[1].v = 0x1;
-    emulator.run(None);
+    emulator.run().unwrap();
     assert!(
         emulator.pc == 0x202,
         "PC should be 0x202 after opcode 0x5120"
     );
     emulator.registers[3].v = 0x4;
     emulator.registers[4].v = 0x5;
-    emulator.run(None);
+    emulator.run().unwrap();
     assert!(
         emulator.pc == 0x204,
         "PC should be 0x204 after opcode 0x5340"
@@ -135,7 +158,7 @@ fn test_opcode_6_x_nn() {
     let mut emulator = Emulator::new();
     emulator.load(&program);
 
-    emulator.run(None);
+    emulator.run().unwrap();
     assert!(
         emulator.registers[0].v == 0x01,
         "V0 should be 0x1 after opcode 0x6001"
@@ -144,8 +167,8 @@ fn test_opcode_6_x_nn() {
         emulator.registers[1].v == 0x00,
         "V1 should be 0x0 after opcode 0x6102"
     );
-    emulator.run(None);
-    emulator.run(None);
+    emulator.run().unwrap();
+    emulator.run().unwrap();
     assert!(
         emulator.registers[2].v == 0x03,
         "V2 should be 0x3 after opcode 0x6203"
@@ -163,12 +186,12 @@ fn test_opcode_7_x_nn() {
         emulator.registers[0].v == 0x00,
         "V0 should be 0x0 before opcode 0x7002"
     );
-    emulator.run(None);
+    emulator.run().unwrap();
     assert!(
         emulator.registers[0].v == 0x02,
         "V0 should be 0x2 after opcode 0x7002"
     );
-    emulator.run(None);
+    emulator.run().unwrap();
     assert!(
         emulator.registers[0].v == 0x01,
         "V1 should be 0x1 after opcode 0x71FF"
@@ -186,8 +209,8 @@ fn test_opcode_f_x_33() {
     let mut emulator = Emulator::new();
     emulator.load(&program);
 
-    emulator.run(None); // load 0x12 (18) into V0
-    emulator.run(None); // store BCD representation of V0 in memory locations I, I+1, I+2
+    emulator.run().unwrap(); // load 0x12 (18) into V0
+    emulator.run().unwrap(); // store BCD representation of V0 in memory locations I, I+1, I+2
     assert!(
         emulator.memory[emulator.index as usize] == 0,
         "Memory at I should be 0x0 after opcode 0xF033 {:?}",
@@ -201,8 +224,1454 @@ fn test_opcode_f_x_33() {
         emulator.memory[emulator.index as usize + 2] == 8,
         "Memory at I+2 should be 0x2 after opcode 0xF033 {:?}, 0x{:0X?} 0x{:0X?} 0x{:0X?}",
         emulator.index,
-        emulator.memory[emulator.index as usize + 0],
+        emulator.memory[emulator.index as usize],
         emulator.memory[emulator.index as usize + 1],
         emulator.memory[emulator.index as usize + 2]
     );
 }
+
+#[test]
+fn test_opcode_e_x_9e() {
+    // test EX9E opcode (skip if key Vx is pressed)
+    let program = create_buffer(vec![0x60, 0x05, 0xE0, 0x9E, 0xFF, 0xFF, 0xE0, 0x9E]);
+    let mut emulator = Emulator::new();
+    emulator.load(&program);
+
+    emulator.run().unwrap(); // load 0x5 into V0
+    emulator.run().unwrap();
+    assert!(
+        emulator.pc == 0x204,
+        "PC should be 0x204 after opcode 0xE09E without key 5 pressed"
+    );
+    emulator.key_buffer.press(0x3);
+    emulator.key_buffer.press(0x5);
+    emulator.pc = 0x202;
+    emulator.run().unwrap();
+    assert!(
+        emulator.pc == 0x206,
+        "PC should be 0x206 after opcode 0xE09E with key 5 pressed"
+    );
+}
+
+#[test]
+fn test_opcode_e_x_a1() {
+    // test EXA1 opcode (skip if key Vx is not pressed)
+    let program = create_buffer(vec![0x60, 0x05, 0xE0, 0xA1, 0xFF, 0xFF, 0xE0, 0xA1]);
+    let mut emulator = Emulator::new();
+    emulator.load(&program);
+
+    emulator.run().unwrap(); // load 0x5 into V0
+    emulator.key_buffer.press(0x5);
+    emulator.key_buffer.press(0x6);
+    emulator.key_buffer.release(0x6);
+    emulator.run().unwrap();
+    assert!(
+        emulator.pc == 0x204,
+        "PC should be 0x204 after opcode 0xE0A1 with key 5 pressed"
+    );
+    emulator.key_buffer.release(0x5);
+    emulator.pc = 0x202;
+    emulator.run().unwrap();
+    assert!(
+        emulator.pc == 0x206,
+        "PC should be 0x206 after opcode 0xE0A1 without key 5 pressed"
+    );
+}
+
+#[test]
+fn test_opcode_f_x_0a() {
+    // test FX0A opcode (wait for key press and release)
+    let program = create_buffer(vec![0xF3, 0x0A]);
+    let mut emulator = Emulator::new();
+    emulator.load(&program);
+
+    emulator.run().unwrap();
+    assert!(
+        emulator.pc == 0x200,
+        "PC should stay at 0x200 without a key"
+    );
+    emulator.key_buffer.press(0xA);
+    emulator.run().unwrap();
+    emulator.run().unwrap();
+    assert!(
+        emulator.pc == 0x200,
+        "PC should stay at 0x200 while the key is held"
+    );
+    emulator.key_buffer.release(0xA);
+    emulator.run().unwrap();
+    assert!(
+        emulator.pc == 0x202,
+        "PC should be 0x202 after the key is released"
+    );
+    assert!(emulator.registers[3].v == 0xA, "V3 should hold key 0xA");
+}
+
+#[test]
+fn test_quirk_shift() {
+    // test 8XY6 opcode with and without the shift quirk
+    let program = create_buffer(vec![0x61, 0x04, 0x80, 0x16]);
+    let mut emulator = Emulator::with_quirks(Quirks::vip());
+    emulator.load(&program);
+    emulator.registers[0].v = 0x81;
+    emulator.run().unwrap();
+    emulator.run().unwrap();
+    assert!(
+        emulator.registers[0].v == 0x02 && emulator.registers[0xF].v == 0,
+        "V0 should be V1 >> 1 with the COSMAC VIP quirks"
+    );
+
+    let mut emulator = Emulator::with_quirks(Quirks::schip());
+    emulator.load(&program);
+    emulator.registers[0].v = 0x81;
+    emulator.run().unwrap();
+    emulator.run().unwrap();
+    assert!(
+        emulator.registers[0].v == 0x40 && emulator.registers[0xF].v == 1,
+        "V0 should be V0 >> 1 with the SUPER-CHIP quirks"
+    );
+}
+
+#[test]
+fn test_quirk_jump() {
+    // test BNNN opcode with and without the jump quirk
+    let program = create_buffer(vec![0xB3, 0x00]);
+    let mut emulator = Emulator::with_quirks(Quirks::vip());
+    emulator.load(&program);
+    emulator.registers[0].v = 0x10;
+    emulator.registers[3].v = 0x20;
+    emulator.run().unwrap();
+    assert!(emulator.pc == 0x310, "PC should be 0x300 + V0");
+
+    let mut emulator = Emulator::with_quirks(Quirks::chip48());
+    emulator.load(&program);
+    emulator.registers[0].v = 0x10;
+    emulator.registers[3].v = 0x20;
+    emulator.run().unwrap();
+    assert!(emulator.pc == 0x320, "PC should be 0x300 + V3");
+}
+
+#[test]
+fn test_quirk_index_increment() {
+    // test FX55 opcode with the different index quirks
+    let program = create_buffer(vec![0xA3, 0x00, 0xF2, 0x55]);
+    for (quirks, index) in [
+        (Quirks::vip(), 0x303),
+        (Quirks::chip48(), 0x302),
+        (Quirks::schip(), 0x300),
+    ] {
+        let mut emulator = Emulator::with_quirks(quirks);
+        emulator.load(&program);
+        emulator.run().unwrap();
+        emulator.run().unwrap();
+        assert!(
+            emulator.index == index,
+            "I should be 0x{:03X} after opcode 0xF255",
+            index
+        );
+    }
+}
+
+#[test]
+fn test_quirk_clipping() {
+    // draw the 0 font sprite at the bottom right corner
+    let program = create_buffer(vec![0x60, 0x3E, 0x61, 0x1E, 0xA0, 0x50, 0xD0, 0x15]);
+    let mut emulator = Emulator::with_quirks(Quirks::xochip());
+    emulator.load(&program);
+    for _ in 0..4 {
+        emulator.run().unwrap();
+    }
+    assert!(
+        emulator.graphics.pixel(1, 0),
+        "Sprite should wrap around to the top left corner"
+    );
+
+    let mut emulator = Emulator::with_quirks(Quirks::schip());
+    emulator.load(&program);
+    for _ in 0..4 {
+        emulator.run().unwrap();
+    }
+    assert!(
+        emulator.graphics.pixel(63, 30) && !emulator.graphics.pixel(1, 0),
+        "Sprite should be clipped at the screen edges"
+    );
+}
+
+#[test]
+fn test_quirk_display_wait() {
+    // test DXYN opcode waiting for the vertical blank
+    let program = create_buffer(vec![0xD0, 0x05, 0xD0, 0x05]);
+    let mut emulator = Emulator::with_quirks(Quirks::vip());
+    emulator.load(&program);
+    emulator.run().unwrap();
+    assert!(
+        emulator.pc == 0x200,
+        "DXYN should wait for the vertical blank"
+    );
+    emulator.vblank = true;
+    emulator.run().unwrap();
+    emulator.run().unwrap();
+    assert!(
+        emulator.pc == 0x202,
+        "Only one sprite should be drawn per frame"
+    );
+
+    let mut emulator = Emulator::new();
+    emulator.load(&program);
+    emulator.run().unwrap();
+    emulator.run().unwrap();
+    assert!(
+        emulator.pc == 0x204,
+        "The default quirks should not wait for a timer tick"
+    );
+}
+
+#[test]
+fn test_schip_hires_sprite() {
+    // switch to hires and draw a 16x16 sprite at 120, 60
+    let mut program = vec![0x00, 0xFF, 0x60, 0x78, 0x61, 0x3C, 0xA2, 0x10, 0xD0, 0x10];
+    program.resize(0x10, 0);
+    program.extend([0xFF; 32]);
+    let program = create_buffer(program);
+    let mut emulator = Emulator::with_platform(Platform::SuperChip, Quirks::schip());
+    emulator.load(&program);
+    for _ in 0..5 {
+        emulator.run().unwrap();
+    }
+    assert!(
+        emulator.graphics.width() == 128,
+        "Display should be 128 wide"
+    );
+    assert!(
+        emulator.graphics.pixel(127, 63) && emulator.graphics.pixel(120, 60),
+        "16x16 sprite should be drawn in the bottom right corner"
+    );
+    assert!(
+        !emulator.graphics.pixel(0, 0),
+        "16x16 sprite should be clipped at the edges"
+    );
+}
+
+#[test]
+fn test_schip_scroll() {
+    // draw the 0 font sprite at 0, 0 and scroll it
+    let program = create_buffer(vec![
+        0xA0, 0x50, 0xD0, 0x05, 0x00, 0xC2, 0x00, 0xFB, 0x00, 0xFC,
+    ]);
+    let mut emulator = Emulator::with_platform(Platform::SuperChip, Quirks::schip());
+    emulator.load(&program);
+    emulator.run().unwrap();
+    emulator.run().unwrap();
+    emulator.run().unwrap();
+    assert!(
+        !emulator.graphics.pixel(0, 0) && emulator.graphics.pixel(0, 2),
+        "Sprite should be scrolled down 2 pixels"
+    );
+    emulator.run().unwrap();
+    assert!(
+        !emulator.graphics.pixel(0, 2) && emulator.graphics.pixel(4, 2),
+        "Sprite should be scrolled right 4 pixels"
+    );
+    emulator.run().unwrap();
+    assert!(
+        emulator.graphics.pixel(0, 2) && !emulator.graphics.pixel(4, 2),
+        "Sprite should be scrolled left 4 pixels"
+    );
+}
+
+#[test]
+fn test_schip_rpl_flags_and_big_font() {
+    // test FX75, FX85 and FX30 opcodes
+    let program = create_buffer(vec![
+        0x60, 0x11, 0x61, 0x07, 0xF1, 0x75, 0x60, 0x00, 0xF1, 0x85, 0xF1, 0x30,
+    ]);
+    let mut emulator = Emulator::with_platform(Platform::SuperChip, Quirks::schip());
+    emulator.load(&program);
+    for _ in 0..5 {
+        emulator.run().unwrap();
+    }
+    assert!(
+        emulator.registers[0].v == 0x11 && emulator.registers[1].v == 0x07,
+        "V0 and V1 should be restored from the RPL flags"
+    );
+    emulator.run().unwrap();
+    assert!(
+        emulator.index == 0x0A0 + 7 * 10,
+        "I should point to the big sprite for 7"
+    );
+}
+
+#[test]
+fn test_xochip_long_index() {
+    // test F000 NNNN opcode and skipping over it
+    let program = create_buffer_sized(
+        vec![
+            0xF0, 0x00, 0x12, 0x34, 0x30, 0x00, 0xF0, 0x00, 0xAB, 0xCD, 0x00, 0xE0,
+        ],
+        0x10000,
+    );
+    let mut emulator = Emulator::with_platform(Platform::XoChip, Quirks::xochip());
+    emulator.load(&program);
+    emulator.run().unwrap();
+    assert!(emulator.index == 0x1234, "I should be 0x1234");
+    assert!(emulator.pc == 0x204, "PC should be 0x204 after F000 NNNN");
+    emulator.run().unwrap();
+    assert!(
+        emulator.pc == 0x20A,
+        "PC should skip over the whole F000 NNNN instruction"
+    );
+}
+
+#[test]
+fn test_xochip_register_range() {
+    // test 5XY2 and 5XY3 opcodes
+    let program = create_buffer_sized(
+        vec![0xA3, 0x00, 0x52, 0x42, 0x54, 0x23, 0x51, 0x33],
+        0x10000,
+    );
+    let mut emulator = Emulator::with_platform(Platform::XoChip, Quirks::xochip());
+    emulator.load(&program);
+    emulator.registers[2].v = 0x22;
+    emulator.registers[3].v = 0x33;
+    emulator.registers[4].v = 0x44;
+    emulator.run().unwrap();
+    emulator.run().unwrap();
+    assert!(
+        emulator.memory[0x300..0x303] == [0x22, 0x33, 0x44],
+        "V2 to V4 should be stored at I"
+    );
+    assert!(emulator.index == 0x300, "I should not change");
+    emulator.run().unwrap();
+    assert!(
+        emulator.registers[4].v == 0x22 && emulator.registers[2].v == 0x44,
+        "V4 down to V2 should be loaded from I"
+    );
+    emulator.run().unwrap();
+    assert!(
+        emulator.registers[1].v == 0x22 && emulator.registers[2].v == 0x33,
+        "V1 to V3 should be loaded from I"
+    );
+}
+
+#[test]
+fn test_xochip_bitplanes() {
+    // select both planes and draw a 1 row sprite with different data per plane
+    let mut program = vec![0xF3, 0x01, 0xA2, 0x10, 0xD0, 0x01, 0xF2, 0x01, 0x00, 0xE0];
+    program.resize(0x10, 0);
+    program.extend([0xC0, 0xA0]);
+    let program = create_buffer_sized(program, 0x10000);
+    let mut emulator = Emulator::with_platform(Platform::XoChip, Quirks::xochip());
+    emulator.load(&program);
+    for _ in 0..3 {
+        emulator.run().unwrap();
+    }
+    assert!(
+        emulator.graphics.color(0, 0) == 3
+            && emulator.graphics.color(1, 0) == 1
+            && emulator.graphics.color(2, 0) == 2,
+        "Pixels should be set on the planes from their own sprite data"
+    );
+    emulator.run().unwrap();
+    emulator.run().unwrap();
+    assert!(
+        emulator.graphics.color(0, 0) == 1 && emulator.graphics.color(2, 0) == 0,
+        "Clearing should only affect the selected plane"
+    );
+}
+
+#[test]
+fn test_errors() {
+    // unknown opcode
+    let program = create_buffer(vec![0x00, 0x00]);
+    let mut emulator = Emulator::new();
+    emulator.load(&program);
+    assert!(
+        emulator.run()
+            == Err(Chip8Error::UnknownOpcode {
+                address: 0x200,
+                opcode: 0x0000
+            }),
+        "0x0000 should be an unknown opcode"
+    );
+    assert!(
+        emulator.pc == 0x200,
+        "PC should stay at the faulting instruction"
+    );
+
+    // return with an empty stack
+    let program = create_buffer(vec![0x00, 0xEE]);
+    let mut emulator = Emulator::new();
+    emulator.load(&program);
+    assert!(
+        emulator.run()
+            == Err(Chip8Error::StackUnderflow {
+                address: 0x200,
+                opcode: 0x00EE
+            }),
+        "0x00EE should underflow the stack"
+    );
+
+    // recursive call
+    let program = create_buffer(vec![0x22, 0x00]);
+    let mut emulator = Emulator::new();
+    emulator.load(&program);
+    for _ in 0..16 {
+        emulator.run().unwrap();
+    }
+    assert!(
+        emulator.run()
+            == Err(Chip8Error::StackOverflow {
+                address: 0x200,
+                opcode: 0x2200
+            }),
+        "the 17th call should overflow the stack"
+    );
+
+    // BCD at the end of memory
+    let program = create_buffer(vec![0xAF, 0xFF, 0xF0, 0x33]);
+    let mut emulator = Emulator::new();
+    emulator.load(&program);
+    emulator.run().unwrap();
+    assert!(
+        emulator.run()
+            == Err(Chip8Error::MemoryOutOfRange {
+                address: 0x202,
+                opcode: 0xF033,
+                target: 0x1000
+            }),
+        "FX33 should not write past the end of memory"
+    );
+
+    // PC at the end of memory
+    let program = create_buffer(vec![0x1F, 0xFF]);
+    let mut emulator = Emulator::new();
+    emulator.load(&program);
+    emulator.run().unwrap();
+    assert!(
+        emulator.run() == Err(Chip8Error::PcOutOfRange { pc: 0xFFF }),
+        "PC should not leave memory"
+    );
+}
+
+#[test]
+fn test_headless_frontend() {
+    // run until 00FD without any SDL frontend
+    let program = create_buffer(vec![0xA0, 0x50, 0xD0, 0x05, 0x00, 0xFD]);
+    let mut frontend = Headless::new();
+    emulate(&program, &Config::new(Platform::SuperChip), &mut frontend);
+    assert!(frontend.frames > 0, "A frame should have been presented");
+}
+
+#[test]
+fn test_run_frame_timers() {
+    // set the delay timer to 5 and count down in a loop
+    let program = create_buffer(vec![0x60, 0x05, 0xF0, 0x15, 0xF1, 0x07, 0x12, 0x04]);
+    let mut emulator = Emulator::new();
+    emulator.load(&program);
+    emulator.instructions_per_frame = 4;
+    emulator.run_frame().unwrap();
+    assert!(
+        emulator.delay_timer == 4,
+        "Delay timer should tick once per frame"
+    );
+    assert!(emulator.vblank, "Vertical blank should be signalled");
+    for _ in 0..10 {
+        emulator.run_frame().unwrap();
+    }
+    assert!(
+        emulator.delay_timer == 0 && emulator.registers[1].v == 0,
+        "Delay timer should stop at 0"
+    );
+}
+
+#[test]
+fn test_instructions_per_frame() {
+    // count up V0 in a loop, one frame should run exactly the configured instructions
+    let program = create_buffer(vec![0x70, 0x01, 0x12, 0x00]);
+    let mut config = Config::new(Platform::Chip8);
+    config.set_instructions_per_second(600);
+    assert!(
+        config.instructions_per_frame == 10,
+        "600 ips should be 10 ipf"
+    );
+    let mut emulator = Emulator::with_config(&config);
+    emulator.load(&program);
+    emulator.run_frame().unwrap();
+    emulator.run_frame().unwrap();
+    assert!(
+        emulator.registers[0].v == 10,
+        "V0 should be incremented 5 times per frame"
+    );
+}
+
+#[test]
+fn test_debugger_continue_frame() {
+    // 7001 adds to V0 in a loop, the delay timer counts the frames
+    let program = create_buffer(vec![0x70, 0x01, 0x12, 0x00]);
+    let mut emulator = Emulator::new();
+    emulator.load(&program);
+    emulator.instructions_per_frame = 10;
+    emulator.delay_timer = 5;
+    let mut debugger = Debugger::new();
+    for frame in 1..=3 {
+        debugger.continue_frame(&mut emulator);
+        assert!(
+            emulator.cycles == 10 * frame,
+            "Continuing should run instructions_per_frame instructions per frame"
+        );
+        assert!(
+            emulator.delay_timer == 5 - frame as u16,
+            "Timers should tick once per continued frame"
+        );
+    }
+}
+
+#[test]
+fn test_seeded_random() {
+    // fill V0..V7 with random bytes
+    let mut bytes = Vec::new();
+    for x in 0..8 {
+        bytes.extend_from_slice(&[0xC0 | x, 0xFF]);
+    }
+    let program = create_buffer(bytes);
+    let mut config = Config::new(Platform::Chip8);
+    config.seed = Some(1234);
+    let run = |config: &Config| {
+        let mut emulator = Emulator::with_config(config);
+        emulator.load(&program);
+        for _ in 0..8 {
+            emulator.run().unwrap();
+        }
+        emulator.registers.map(|r| r.v)
+    };
+    let first = run(&config);
+    assert!(
+        first == run(&config),
+        "Same seed should give the same values"
+    );
+    assert!(
+        first[..8].iter().any(|&v| v != first[0]),
+        "CXNN should not repeat the same value"
+    );
+    config.seed = Some(4321);
+    assert!(
+        first != run(&config),
+        "Other seeds should give other values"
+    );
+}
+
+#[test]
+fn test_beeper_envelope() {
+    let settings = AudioSettings {
+        waveform: Waveform::Square,
+        frequency: 441.0,
+        volume: 1.0,
+    };
+    let mut beeper = Beeper::new(settings, 44100);
+    let mut out = [1.0; 64];
+    beeper.fill(&mut out);
+    assert!(out.iter().all(|&s| s == 0.0), "Beeper should start silent");
+
+    beeper.playing = true;
+    beeper.fill(&mut out);
+    assert!(
+        out[0].abs() < 0.01,
+        "Tone should fade in instead of jumping to full volume"
+    );
+    let mut out = [0.0; 1000];
+    beeper.fill(&mut out);
+    assert!(out.contains(&1.0), "Tone should reach full volume");
+
+    beeper.playing = false;
+    let last = out[999];
+    beeper.fill(&mut out);
+    assert!(
+        (out[0] - last).abs() < 0.1,
+        "Tone should fade out instead of stopping at once"
+    );
+    assert!(out[999] == 0.0, "Tone should be silent after fading out");
+}
+
+#[test]
+fn test_beeper_mute() {
+    let mut beeper = Beeper::new(AudioSettings::default(), 44100);
+    beeper.playing = true;
+    beeper.muted = true;
+    let mut out = [1.0; 1000];
+    beeper.fill(&mut out);
+    assert!(
+        out.iter().all(|&s| s == 0.0),
+        "Muted beeper should be silent"
+    );
+}
+
+#[test]
+fn test_beeper_pattern() {
+    // F002 loads the pattern at 0x20C, FX3A sets the pitch, FX18 starts the sound timer
+    let program = create_buffer_sized(
+        vec![
+            0xA2, 0x0C, 0xF0, 0x02, 0x60, 0x40, 0xF0, 0x3A, 0xF0, 0x18, 0x12, 0x0A, 0xF0, 0xF0,
+            0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0,
+        ],
+        0x10000,
+    );
+    let mut emulator = Emulator::with_platform(Platform::XoChip, Quirks::xochip());
+    emulator.load(&program);
+    emulator.sound_timer = 1;
+    assert!(
+        emulator.sound() == Some(Sound::Tone),
+        "The tone should play until F002 loads a pattern"
+    );
+    for _ in 0..5 {
+        emulator.run().unwrap();
+    }
+    let sound = emulator.sound();
+    assert!(
+        sound
+            == Some(Sound::Pattern {
+                pattern: [0xF0; 16],
+                rate: 4000.0
+            }),
+        "Pitch 64 should play the pattern at 4000 bits per second, got {:?}",
+        sound
+    );
+
+    // one bit per sample: four high samples, then four low ones
+    let mut beeper = Beeper::new(AudioSettings::default(), 4000);
+    beeper.settings.volume = 1.0;
+    beeper.play(sound);
+    let mut out = [0.0; 200];
+    beeper.fill(&mut out);
+    let start = out.iter().rposition(|&s| s.abs() < 1.0).unwrap() + 1;
+    let start = start + (8 - start % 8) % 8;
+    assert!(
+        out[start..start + 8] == [1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0],
+        "Beeper should play the bits of the pattern, got {:?}",
+        &out[start..start + 8]
+    );
+}
+
+#[test]
+fn test_savestate_round_trip() {
+    // draw a sprite, call a subroutine and draw random numbers
+    let program = create_buffer(vec![
+        0xA0, 0x50, 0xD0, 0x15, 0x22, 0x08, 0x00, 0x00, 0xC1, 0xFF, 0xF2, 0x15, 0x12, 0x08,
+    ]);
+    let mut config = Config::new(Platform::Chip8);
+    config.seed = Some(7);
+    config.quirks.display_wait = false;
+    let mut emulator = Emulator::with_config(&config);
+    emulator.load(&program);
+    for _ in 0..4 {
+        emulator.run().unwrap();
+    }
+    emulator.key_buffer.press(0xA);
+    let state = savestate::save(&emulator);
+
+    let mut expected = Vec::new();
+    for _ in 0..10 {
+        emulator.run().unwrap();
+        expected.push(emulator.registers[1].v);
+    }
+
+    let mut restored = Emulator::with_config(&Config::new(Platform::Chip8));
+    savestate::load(&mut restored, &state).unwrap();
+    assert!(restored.pc == 0x20A, "PC should be restored");
+    assert!(restored.stack == vec![0x206], "Stack should be restored");
+    assert!(
+        restored.cycles == 4,
+        "The instruction counter should be restored"
+    );
+    assert!(restored.graphics.pixel(0, 0), "Screen should be restored");
+    assert!(
+        restored.key_buffer.is_pressed(0xA),
+        "Keys should be restored"
+    );
+    let mut actual = Vec::new();
+    for _ in 0..10 {
+        restored.run().unwrap();
+        actual.push(restored.registers[1].v);
+    }
+    assert!(
+        actual == expected,
+        "Restored RNG should continue the same sequence"
+    );
+}
+
+#[test]
+fn test_savestate_rejects_corruption() {
+    let emulator = Emulator::new();
+    let state = savestate::save(&emulator);
+    let mut target = Emulator::new();
+
+    let mut corrupted = state.clone();
+    *corrupted.last_mut().unwrap() ^= 0xFF;
+    assert!(matches!(
+        savestate::load(&mut target, &corrupted),
+        Err(StateError::ChecksumMismatch)
+    ));
+    let mut newer = state.clone();
+    newer[4] = 99;
+    assert!(matches!(
+        savestate::load(&mut target, &newer),
+        Err(StateError::UnsupportedVersion(99))
+    ));
+    assert!(matches!(
+        savestate::load(&mut target, &state[..100]),
+        Err(StateError::Truncated)
+    ));
+    assert!(matches!(
+        savestate::load(
+            &mut Emulator::with_config(&Config::new(Platform::XoChip)),
+            &state
+        ),
+        Err(StateError::PlatformMismatch)
+    ));
+    let mut planes = Emulator::new();
+    planes.graphics.planes = 4;
+    let mut deep = Emulator::new();
+    deep.stack = vec![0x200; 17];
+    for broken in [planes, deep] {
+        assert!(
+            matches!(
+                savestate::load(&mut target, &savestate::save(&broken)),
+                Err(StateError::Invalid(_))
+            ),
+            "Fields out of range should be rejected even with a valid checksum"
+        );
+    }
+    assert!(
+        savestate::crc32(b"123456789") == 0xCBF4_3926,
+        "CRC-32 should match the IEEE check value"
+    );
+}
+
+#[test]
+fn test_rewind() {
+    // count up V0 and draw it as a digit every frame
+    let program = create_buffer(vec![
+        0x70, 0x01, 0x00, 0xE0, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x00,
+    ]);
+    let mut emulator = Emulator::new();
+    emulator.load(&program);
+    emulator.quirks.display_wait = false;
+    emulator.instructions_per_frame = 5;
+    let mut rewind = Rewind::new(1);
+    rewind.push(&emulator);
+    let mut states = vec![savestate::save(&emulator)];
+    for _ in 0..100 {
+        emulator.run_frame().unwrap();
+        rewind.push(&emulator);
+        states.push(savestate::save(&emulator));
+    }
+    assert!(
+        rewind.len() == 60,
+        "Rewind should keep one second of frames"
+    );
+    assert!(
+        rewind.memory_usage() < 2 * states[0].len(),
+        "Frames should be stored as small deltas"
+    );
+    for frame in (40..100).rev() {
+        assert!(rewind.step_back(&mut emulator), "Frame should be available");
+        assert!(
+            savestate::save(&emulator) == states[frame],
+            "Rewind should restore frame {}",
+            frame
+        );
+    }
+    assert!(
+        !rewind.step_back(&mut emulator),
+        "Frames older than the capacity should be dropped"
+    );
+}
+
+#[test]
+fn test_movie_record_and_replay() {
+    // draw random digits, plus one while key 5 is held, then exit
+    let program = create_buffer(vec![
+        0x60, 0x05, 0xC1, 0x0F, 0xE5, 0x9E, 0x12, 0x0A, 0x71, 0x01, 0x00, 0xE0, 0xF1, 0x29, 0xD2,
+        0x25, 0x70, 0xFF, 0x30, 0x00, 0x12, 0x02, 0x00, 0xFD,
+    ]);
+    let path = std::env::temp_dir().join(format!("chip8-test-{}.c8m", std::process::id()));
+    let mut config = Config::new(Platform::SuperChip);
+    config.instructions_per_frame = 4;
+    config.record = Some(path.clone());
+    let mut frontend = Headless::new();
+    frontend.keys.press(5);
+    emulate(&program, &config, &mut frontend);
+
+    let movie = Movie::load_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(movie.frames.len() > 1, "Every frame should be recorded");
+    assert!(
+        movie.frames.iter().all(|&keys| keys == 1 << 5),
+        "Held keys should be recorded"
+    );
+    assert!(
+        Movie::from_bytes(&movie.to_bytes()).unwrap() == movie,
+        "Movie should survive a round trip"
+    );
+    let replayed = movie::replay(&program, &movie).unwrap();
+    assert!(replayed.exited, "Replay should run to the end");
+    assert!(
+        replayed.graphics.digest() == frontend.graphics.digest(),
+        "Replay should end on the same frame"
+    );
+}
+
+#[test]
+fn test_trace() {
+    let program = create_buffer(vec![0x60, 0x05, 0xA2, 0x34, 0x70, 0x01]);
+    let path = std::env::temp_dir().join(format!("chip8-test-{}.trace", std::process::id()));
+    let mut config = Config::new(Platform::Chip8);
+    config.trace = Some(TraceSettings {
+        path: path.clone(),
+        format: TraceFormat::Text,
+        filter: TraceFilter::default(),
+    });
+    let mut emulator = Emulator::with_config(&config);
+    emulator.load(&program);
+    emulator.tracer = Tracer::from_config(&config);
+    for _ in 0..3 {
+        emulator.run().unwrap();
+    }
+    assert!(emulator.run().is_err(), "0000 should be an unknown opcode");
+    assert!(
+        emulator.cycles == 3,
+        "Instructions that failed should not be counted"
+    );
+    emulator.tracer = None;
+    let trace = std::fs::read_to_string(&path).unwrap();
+    let lines = trace.lines().collect::<Vec<_>>();
+    assert!(
+        lines.len() == 3,
+        "Every executed instruction and only those should be traced"
+    );
+    assert!(
+        lines[2].starts_with(
+            "00000002 0204 7001 V 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0234 SP 0 DT 00 ST 00 ; "
+        ),
+        "Trace should hold the state before the instruction: {}",
+        lines[2]
+    );
+
+    // FX0A is logged once the key was pressed and released, F000 NNNN with its address
+    let waiting = create_buffer_sized(vec![0xF0, 0x0A, 0xF0, 0x00, 0x12, 0x34], 0x10000);
+    let mut xochip = Config::new(Platform::XoChip);
+    xochip.trace = Some(TraceSettings {
+        path: path.clone(),
+        format: TraceFormat::Text,
+        filter: TraceFilter::default(),
+    });
+    let mut emulator = Emulator::with_config(&xochip);
+    emulator.load(&waiting);
+    emulator.tracer = Tracer::from_config(&xochip);
+    emulator.key_buffer.press(0x5);
+    for _ in 0..3 {
+        emulator.run().unwrap();
+    }
+    emulator.key_buffer.release(0x5);
+    emulator.run().unwrap();
+    emulator.run().unwrap();
+    emulator.tracer = None;
+    let trace = std::fs::read_to_string(&path).unwrap();
+    let lines = trace.lines().collect::<Vec<_>>();
+    assert!(
+        lines.len() == 2 && emulator.cycles == 2,
+        "Waiting for a key should be logged and counted once: {:?}",
+        lines
+    );
+    assert!(
+        lines[1].ends_with("; set index to 0x1234"),
+        "F000 NNNN should be traced with its address: {}",
+        lines[1]
+    );
+
+    config.trace = Some(TraceSettings {
+        path: path.clone(),
+        format: TraceFormat::Binary,
+        filter: TraceFilter {
+            addresses: trace::parse_range("200-203"),
+            classes: trace::parse_classes("6,7"),
+        },
+    });
+    let mut emulator = Emulator::with_config(&config);
+    emulator.load(&program);
+    emulator.tracer = Tracer::from_config(&config);
+    for _ in 0..3 {
+        emulator.run().unwrap();
+    }
+    emulator.tracer = None;
+    let trace = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        trace.len() == 8 + 34,
+        "Only 6XNN at 0x200 should pass the filters"
+    );
+    assert!(
+        trace[..4] == *b"C8TR",
+        "Binary trace should start with magic"
+    );
+}
+
+#[test]
+fn test_screenshot() {
+    // draw the font digit 0 in the top left corner
+    let program = create_buffer(vec![0xA0, 0x50, 0xD0, 0x05]);
+    let mut emulator = Emulator::with_quirks(Quirks::chip48());
+    emulator.load(&program);
+    emulator.run().unwrap();
+    emulator.run().unwrap();
+    let palette = [[0, 0, 0], [10, 20, 30], [0, 0, 0], [0, 0, 0]];
+
+    let mut pbm = Vec::new();
+    screenshot::write(&emulator.graphics, &mut pbm, ImageFormat::Pbm, &palette, 1).unwrap();
+    assert!(pbm.starts_with(b"P4\n64 32\n"), "PBM should be native size");
+    assert!(pbm[9] == 0x0F, "Lit pixels should be white in the PBM");
+
+    let mut ppm = Vec::new();
+    screenshot::write(&emulator.graphics, &mut ppm, ImageFormat::Ppm, &palette, 2).unwrap();
+    assert!(
+        ppm.starts_with(b"P6\n128 64\n255\n"),
+        "PPM should be scaled"
+    );
+    assert!(
+        ppm[14..20] == [10, 20, 30, 10, 20, 30],
+        "PPM should use the palette"
+    );
+
+    let mut png = Vec::new();
+    screenshot::write(&emulator.graphics, &mut png, ImageFormat::Png, &palette, 10).unwrap();
+    let mut reader = png::Decoder::new(&png[..]).read_info().unwrap();
+    let mut rgb = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut rgb).unwrap();
+    assert!(
+        (info.width, info.height) == (640, 320),
+        "PNG should be scaled to the window size"
+    );
+    assert!(rgb[..3] == [10, 20, 30], "PNG should use the palette");
+}
+
+#[test]
+fn test_video() {
+    let blank = Emulator::new().graphics;
+    let program = create_buffer(vec![0xA0, 0x50, 0xD0, 0x05]);
+    let mut emulator = Emulator::with_quirks(Quirks::chip48());
+    emulator.load(&program);
+    emulator.run().unwrap();
+    emulator.run().unwrap();
+    let digit = emulator.graphics;
+    let frames = [&blank, &blank, &blank, &digit, &digit, &blank];
+
+    let dir = std::env::temp_dir();
+    let gif_path = dir.join(format!("chip8-test-{}.gif", std::process::id()));
+    let mut recorder = VideoRecorder::create(&gif_path, &PALETTE, 2).unwrap();
+    for graphics in frames {
+        recorder.push(graphics).unwrap();
+    }
+    assert!(
+        recorder.frames() == 6,
+        "Every pushed frame should be counted"
+    );
+    recorder.finish().unwrap();
+
+    let file = std::fs::File::open(&gif_path).unwrap();
+    let mut decoder = gif::DecodeOptions::new().read_info(file).unwrap();
+    assert!(
+        (decoder.width(), decoder.height()) == (128, 64),
+        "GIF should be scaled"
+    );
+    let mut delays = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        delays.push(frame.delay);
+    }
+    assert!(
+        delays == [5, 3, 2],
+        "Identical frames should be merged, with delays adding up to 60 fps"
+    );
+    std::fs::remove_file(&gif_path).unwrap();
+
+    let ppm_path = dir.join(format!("chip8-test-{}-frames", std::process::id()));
+    let mut recorder = VideoRecorder::create(&ppm_path, &PALETTE, 1).unwrap();
+    for graphics in frames {
+        recorder.push(graphics).unwrap();
+    }
+    recorder.finish().unwrap();
+    let ppm = std::fs::read(ppm_path.join("frame00001.ppm")).unwrap();
+    assert!(
+        ppm.starts_with(b"P6\n128 64\n255\n"),
+        "Low resolution frames should be doubled to the high resolution canvas"
+    );
+    assert!(
+        ppm[14..17] == PALETTE[1],
+        "The digit should be drawn in the second frame"
+    );
+    assert!(
+        !ppm_path.join("frame00003.ppm").exists(),
+        "Identical frames should be merged"
+    );
+    let playlist = std::fs::read_to_string(ppm_path.join("frames.txt")).unwrap();
+    assert!(
+        playlist.contains("file 'frame00000.ppm'\nduration 0.050000"),
+        "The playlist should hold how long each image is shown"
+    );
+    std::fs::remove_dir_all(&ppm_path).unwrap();
+
+    let huge_path = dir.join(format!("chip8-test-{}-huge.gif", std::process::id()));
+    assert!(
+        VideoRecorder::create(&huge_path, &PALETTE, 1024).is_err(),
+        "A canvas wider than a GIF can hold should be refused"
+    );
+    assert!(
+        !huge_path.exists(),
+        "Nothing should be written for a refused recording"
+    );
+}
+
+#[test]
+fn test_display_layout() {
+    assert!(
+        display::viewport((640, 320), (64, 32), Scaling::Integer) == (0, 0, 640, 320),
+        "The default window should be filled"
+    );
+    assert!(
+        display::viewport((1000, 400), (64, 32), Scaling::Integer) == (116, 8, 768, 384),
+        "Integer scaling should use whole pixels and center the picture"
+    );
+    assert!(
+        display::viewport((1000, 400), (128, 64), Scaling::Fit) == (100, 0, 800, 400),
+        "Fit scaling should letterbox to keep the aspect ratio"
+    );
+    assert!(
+        display::parse_color("#FFB000") == Some([255, 176, 0]),
+        "Colours should be parsed as hex"
+    );
+    assert!(
+        display::parse_color("#FFB00").is_none(),
+        "Short colours should be rejected"
+    );
+}
+
+#[test]
+fn test_config_file() {
+    let mut config = Config::default();
+    config
+        .load_str(
+            "[display]\ntheme = \"amber\"\nbackground = \"#102030\"\nscale = 7.5\nscaling = \"fit\"\ngrid = true\n",
+        )
+        .unwrap();
+    let amber = display::theme("amber").unwrap();
+    assert!(
+        config.palette[0] == [16, 32, 48] && config.palette[1] == amber[1],
+        "Single colours should replace parts of the theme"
+    );
+    assert!(
+        config.display.scale == 7.5 && config.display.scaling == Scaling::Fit,
+        "Scaling should be read from the file"
+    );
+    assert!(config.display.grid && config.display.resizable);
+
+    assert!(
+        matches!(
+            config.load_str("[display]\nscale = \"big\"\n"),
+            Err(ConfigError::Invalid { .. })
+        ),
+        "Settings of the wrong kind should be rejected"
+    );
+    assert!(
+        matches!(
+            config.load_str("[display]\nzoom = 2\n"),
+            Err(ConfigError::Invalid { .. })
+        ),
+        "Unknown settings should be rejected"
+    );
+    assert!(
+        matches!(config.load_str("[display"), Err(ConfigError::Parse(_))),
+        "Broken TOML should be rejected"
+    );
+
+    // the database goes over the global file, <rom>.toml over the database
+    let dir = std::env::temp_dir().join(format!("chip8-test-{}-layers", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let global = dir.join("config.toml");
+    let rom = dir.join("rom.ch8.toml");
+    std::fs::write(
+        &global,
+        "[display]\ntheme = \"amber\"\n[keys]\npreset = \"qwerty\"\n",
+    )
+    .unwrap();
+    std::fs::write(&rom, "[display]\nforeground = \"#102030\"\n").unwrap();
+    let profile = database::Profile {
+        colors: vec![[1, 2, 3], [4, 5, 6]],
+        keys: vec![(String::from("up"), 0x5)],
+        ..Default::default()
+    };
+    let mut config = Config::default();
+    config
+        .load_layers(Some(&global), Some(&profile), Some(&rom))
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        config.palette[0] == [1, 2, 3] && config.palette[1] == [16, 32, 48],
+        "ROM settings should win over the database and the database over the global theme"
+    );
+    assert!(
+        config.keymap.key("Up") == Some(0x5) && config.keymap.key("Q") == Some(0x4),
+        "Database keys should be added to the global keymap"
+    );
+    assert!(
+        matches!(
+            config.load_layers(Some(&global), None, None),
+            Err(ConfigError::File(..))
+        ),
+        "Errors should name the file"
+    );
+}
+
+#[test]
+fn test_dirty_flag() {
+    // set V0, draw the font digit 0, then clear the screen
+    let program = create_buffer(vec![0x60, 0x01, 0xA0, 0x50, 0xD0, 0x05, 0x00, 0xE0]);
+    let mut emulator = Emulator::with_quirks(Quirks::chip48());
+    emulator.load(&program);
+    assert!(
+        emulator.graphics.is_dirty(),
+        "A new framebuffer should be drawn once"
+    );
+    emulator.graphics.mark_clean();
+    let state = savestate::save(&emulator);
+
+    emulator.run().unwrap();
+    emulator.run().unwrap();
+    assert!(
+        !emulator.graphics.is_dirty(),
+        "Instructions that do not draw should keep the frame clean"
+    );
+    emulator.run().unwrap();
+    assert!(
+        emulator.graphics.is_dirty(),
+        "DXYN should mark the frame dirty"
+    );
+    emulator.graphics.mark_clean();
+    emulator.run().unwrap();
+    assert!(
+        emulator.graphics.is_dirty(),
+        "00E0 should mark the frame dirty"
+    );
+
+    emulator.graphics.mark_clean();
+    savestate::load(&mut emulator, &state).unwrap();
+    assert!(
+        emulator.graphics.is_dirty(),
+        "Loading a state should mark the frame dirty"
+    );
+}
+
+#[test]
+fn test_keymap() {
+    let qwerty = Keymap::from_preset("qwerty").unwrap();
+    assert!(
+        qwerty.key("1") == Some(0x1)
+            && qwerty.key("x") == Some(0x0)
+            && qwerty.key("V") == Some(0xF),
+        "Presets should follow the keypad layout"
+    );
+    assert!(
+        Keymap::new().key("Y") == Some(0xA),
+        "QWERTZ should be the default"
+    );
+    assert!(Keymap::from_preset("dvorak").is_none());
+
+    let mut keymap = qwerty.clone();
+    let (key, hosts) = keymap::parse_binding("5=Space, Keypad 5").unwrap();
+    keymap.bind(key, &hosts);
+    assert!(
+        keymap.hosts(0x5) == ["Space", "Keypad 5"],
+        "Several host keys should be bound to one key"
+    );
+    assert!(
+        keymap.key("W").is_none(),
+        "Binding should replace the preset keys"
+    );
+    assert!(keymap::parse_binding("G=Space").is_none());
+
+    let mut keys = KeyState::new();
+    let mut held = HeldInputs::new();
+    held.press(Source::Keyboard, "Space", 0x5, &mut keys);
+    held.press(Source::Keyboard, "Keypad 5", 0x5, &mut keys);
+    held.press(Source::Keyboard, "Keypad 5", 0x5, &mut keys);
+    held.release(Source::Keyboard, "Space", 0x5, &mut keys);
+    assert!(
+        keys.is_pressed(0x5),
+        "Key should stay pressed while another host key bound to it is held"
+    );
+    held.release(Source::Keyboard, "Keypad 5", 0x5, &mut keys);
+    assert!(
+        !keys.is_pressed(0x5),
+        "Key should be released with the last host key, repeats count once"
+    );
+    assert!(keymap::parse_binding("5=").is_none());
+
+    let mut config = Config::default();
+    config
+        .load_str("[keys]\npreset = \"azerty\"\nA = [\"Q\", \"Return\"]\n")
+        .unwrap();
+    assert!(
+        config.keymap.key("Z") == Some(0x5) && config.keymap.key("Return") == Some(0xA),
+        "Key bindings should be read from the config file"
+    );
+    assert!(
+        matches!(
+            config.load_str("[keys]\nG = \"Q\"\n"),
+            Err(ConfigError::Invalid { .. })
+        ),
+        "Keys outside the keypad should be rejected"
+    );
+    assert!(
+        matches!(
+            config.load_str("[keys]\n5 = [\"S\", \"Tab\"]\n"),
+            Err(ConfigError::Invalid { .. })
+        ),
+        "Hotkeys should not be bound to the keypad"
+    );
+}
+
+#[test]
+fn test_controller_config() {
+    let mut config = Config::default();
+    assert!(
+        config.controller.key("dpup") == Some(0x2) && config.controller.key("a") == Some(0x5),
+        "The d-pad and face buttons should be mapped by default"
+    );
+    // a ROM that fires with 0 instead of 5
+    config
+        .load_str("[controller]\ndeadzone = 12000\n0 = [\"a\", \"b\"]\n")
+        .unwrap();
+    assert!(config.stick_deadzone == 12000);
+    assert!(
+        config.controller.hosts(0x0) == ["a", "b"] && config.controller.hosts(0x5).is_empty(),
+        "Overrides should replace the buttons of a key"
+    );
+    assert!(
+        config.controller.key("dpup") == Some(0x2),
+        "Other buttons should keep their mapping"
+    );
+    assert!(
+        matches!(
+            config.load_str("[controller]\ndeadzone = 40000\n"),
+            Err(ConfigError::Invalid { .. })
+        ),
+        "The deadzone should be less than the full deflection"
+    );
+
+    // the d-pad of pad 1, its stick and the keyboard all hold 2, pad 2 holds 5
+    let mut keys = KeyState::new();
+    let mut held = HeldInputs::new();
+    held.press(Source::Buttons(1), "dpup", 0x2, &mut keys);
+    held.press(Source::Stick(1), "dpup", 0x2, &mut keys);
+    held.press(Source::Keyboard, "W", 0x2, &mut keys);
+    held.press(Source::Buttons(2), "a", 0x5, &mut keys);
+    held.release(Source::Stick(1), "dpup", 0x2, &mut keys);
+    assert!(
+        keys.is_pressed(0x2),
+        "Centering the stick should not release the held d-pad"
+    );
+    held.disconnect(1, &mut keys);
+    assert!(
+        keys.is_pressed(0x2) && keys.is_pressed(0x5),
+        "Unplugging a pad should keep the keys of the keyboard and other pads"
+    );
+    held.release(Source::Keyboard, "W", 0x2, &mut keys);
+    held.disconnect(2, &mut keys);
+    assert!(
+        !keys.is_pressed(0x2) && !keys.is_pressed(0x5),
+        "Keys should be released once nothing holds them"
+    );
+    assert!(
+        super::config::rom_path(std::path::Path::new("roms/pong.ch8"))
+            == std::path::Path::new("roms/pong.ch8.toml"),
+        "ROM settings should be read from next to the ROM"
+    );
+}
+
+#[test]
+fn test_database() {
+    let hashes = r#"{"00112233445566778899aabbccddeeff00112233": 0}"#;
+    let programs = r##"[{
+        "title": "Racer",
+        "roms": {"00112233445566778899aabbccddeeff00112233": {
+            "platforms": ["megachip8", "superchip", "xochip"],
+            "quirkyPlatforms": {"superchip": {"memoryIncrementByX": true, "wrap": true}},
+            "tickrate": 50,
+            "colors": {"pixels": ["#102030", "#FFB000"]},
+            "keys": {"up": 5, "a": 6}
+        }}
+    }]"##;
+    let database = Database::from_json(hashes, programs).unwrap();
+    assert!(
+        database
+            .lookup("0000000000000000000000000000000000000000")
+            .is_none(),
+        "Unknown ROMs should have no profile"
+    );
+    let profile = database
+        .lookup("00112233445566778899AABBCCDDEEFF00112233")
+        .unwrap();
+    let (platform, quirks) = profile.platform.unwrap();
+    assert!(
+        platform == Platform::SuperChip,
+        "The first supported platform should be chosen"
+    );
+    assert!(
+        quirks.index_increment == IndexIncrement::X && !quirks.clip_sprites && quirks.jump_uses_vx,
+        "Quirks of the entry should replace those of the platform"
+    );
+
+    let mut config = Config::default();
+    profile.apply(&mut config);
+    assert!(config.title.as_deref() == Some("Racer"));
+    assert!(config.platform == Platform::SuperChip && config.instructions_per_frame == 50);
+    assert!(config.palette[0] == [16, 32, 48] && config.palette[2] == PALETTE[2]);
+    assert!(
+        config.keymap.key("Up") == Some(5) && config.keymap.key("W") == Some(5),
+        "Controls should be bound in addition to the keymap"
+    );
+    assert!(config.controller.key("a") == Some(6));
+
+    assert!(
+        Database::bundled()
+            .lookup(&database::hash(include_bytes!("../roms/6-keypad.ch8")))
+            .is_some_and(|profile| profile.title == "Keypad test"),
+        "The bundled ROMs should be in the bundled database"
+    );
+    assert!(
+        Database::bundled()
+            .lookup(&database::hash(include_bytes!(
+                "../roms/Keypad Test [Hap, 2006].ch8"
+            )))
+            .and_then(|profile| profile.platform)
+            .is_some_and(|(_, quirks)| !quirks.shift_uses_vy),
+        "Keypad Test should shift VX in place, its key table lookup depends on it"
+    );
+    assert!(matches!(
+        Database::from_json("[]", "[]"),
+        Err(DatabaseError::Invalid(_))
+    ));
+}
+
+#[test]
+fn test_instruction() {
+    for opcode in 0..=0xFFFF {
+        if let Ok(instruction) = Instruction::decode(opcode) {
+            let nibbles = if opcode & 0xF000 == 0x9000 {
+                0xFFF0
+            } else {
+                0xFFFF
+            };
+            assert!(
+                instruction.encode() == opcode & nibbles,
+                "0x{:04X} should encode to the opcode it was decoded from",
+                opcode
+            );
+        }
+    }
+    assert!(Instruction::decode(0x5121).is_err() && Instruction::decode(0xF100).is_err());
+
+    let scroll = Instruction::decode(0x00FB).unwrap();
+    assert!(scroll.available_on(Platform::SuperChip) && !scroll.available_on(Platform::Chip8));
+    assert!(Instruction::decode(0xF000).unwrap().size() == 4);
+    let set_index = Instruction::decode(0xA123).unwrap();
+    assert!(
+        set_index.pattern() == "ANNN" && set_index.address() == Some(0x123),
+        "The address operand should come from the NNN of the pattern"
+    );
+    assert!(Instruction::decode(0x8124).unwrap().address().is_none());
+
+    let or = Instruction::Or { x: 0, y: 1 };
+    let quirks = Quirks::vip();
+    assert!(
+        or.format(Syntax::Description, Platform::Chip8, &quirks)
+            == "set V0 to V0 | V1 and reset VF"
+    );
+    assert!(or.format(Syntax::Classic, Platform::Chip8, &quirks) == "OR V0, V1");
+    assert!(or.format(Syntax::Octo, Platform::Chip8, &quirks) == "v0 |= v1");
+    assert!(
+        Instruction::SkipEqual { x: 3, nn: 0x42 }.octo() == "if v3 != 0x42 then",
+        "Octo should write skips as the condition the next instruction runs under"
+    );
+}
+
+#[test]
+fn test_disassembler() {
+    let mut disassembler = Disassembler::new();
+    // a call to a subroutine at an odd address, and data before and after it
+    let program = [
+        0xA2, 0x09, 0x22, 0x07, 0x12, 0x04, 0xFF, 0x00, 0xEE, 0x3C, 0x42,
+    ];
+    disassembler.memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+    let expected = [
+        "0200: A209      set index to data_209",
+        "0202: 2207      call subroutine at sub_207",
+        "label_204:",
+        "0204: 1204      jump to label_204",
+        "0206: db 0xFF",
+        "sub_207:",
+        "0207: 00EE      return from subroutine",
+        "data_209:",
+        "0209: db 0x3C, 0x42",
+    ];
+    assert!(
+        disassembler.listing() == expected,
+        "unexpected listing {:#?}",
+        disassembler.listing()
+    );
+
+    // both paths of a skip are code, the XO-CHIP long index is skipped as a whole
+    let mut disassembler = Disassembler::with_platform(Platform::XoChip, Quirks::xochip());
+    let program = [
+        0x30, 0x00, 0xF0, 0x00, 0x02, 0x0A, 0x00, 0xFD, 0x00, 0xFD, 0x18,
+    ];
+    disassembler.memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+    let analysis = disassembler.analyze();
+    assert!(analysis.code.keys().copied().eq([0x200, 0x202, 0x206]));
+    assert!(analysis
+        .labels
+        .get(&0x20A)
+        .is_some_and(|label| label == "data_20A"));
+    assert!(disassembler.listing()[1] == "0202: F000 020A set index to data_20A");
+
+    // a jump into the middle of the first instruction, which jumps to 0x212
+    let mut disassembler = Disassembler::new();
+    disassembler.memory[0x200..0x204].copy_from_slice(&[0x60, 0x12, 0x12, 0x01]);
+    disassembler.memory[0x212..0x214].copy_from_slice(&[0x12, 0x12]);
+    let listing = disassembler.listing();
+    assert!(
+        listing[..4]
+            == [
+                "0200: 6012      set V0 to 0x12",
+                "; label_201:",
+                "; 0201: 1212      jump to label_212 (overlaps 0x0200)",
+                "0202: 1201      jump to label_201",
+            ],
+        "Code inside another instruction should be listed as a comment {:#?}",
+        listing
+    );
+    disassembler.syntax = Syntax::Octo;
+    let listing = disassembler.listing();
+    assert!(
+        listing[0] == "v0 := 0x12               # 0200: 6012",
+        "Octo should get the address and opcode as a comment {:#?}",
+        listing
+    );
+    assert!(
+        listing[4] == "0x00 0x00 0x00 0x00 0x00 0x00 0x00 0x00 # 0204",
+        "Octo should get byte literals instead of db {:#?}",
+        listing
+    );
+    let mut disassembler = Disassembler::with_platform(Platform::XoChip, Quirks::xochip());
+    disassembler.syntax = Syntax::Octo;
+    assert!(
+        disassembler.disassemble_with_operand(0xF000, 0x1234) == "i := long 0x1234",
+        "F000 should be written with the address that follows it"
+    );
+}
@@ -0,0 +1,180 @@
+//! Logs of every executed instruction, for comparing runs against other emulators.
+//!
+//! The text format writes one line per instruction with the state before it executes, all numbers in hex
+//! except the decimal cycle number:
+//!
+//! ```text
+//! 00000042 0208 D015 V 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0050 SP 0 DT 00 ST 00 ; display at V0, V1, 0x5, clipped at the edges, after vblank
+//! ```
+//!
+//! Instructions waiting for the vertical blank or a key are logged once, when they complete. F000 NNNN is
+//! disassembled with its address.
+//!
+//! The binary format starts with the magic `C8TR`, a version (u16, currently 1) and the record size
+//! (u16, 34), followed by one record per instruction, all numbers little endian:
+//! cycle (u64), PC (u16), opcode (u16), V0-VF (16 bytes), I (u16), SP (u8), delay timer (u8),
+//! sound timer (u8) and a reserved 0 byte.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::RangeInclusive,
+    path::PathBuf,
+};
+
+use crate::{config::Config, disassembler::Disassembler, emulator::Emulator};
+
+pub const MAGIC: [u8; 4] = *b"C8TR";
+pub const VERSION: u16 = 1;
+const RECORD_SIZE: u16 = 34;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum TraceFormat {
+    #[default]
+    Text,
+    Binary,
+}
+
+/// Which instructions end up in the trace.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TraceFilter {
+    /// Only instructions at these addresses.
+    pub addresses: Option<RangeInclusive<u16>>,
+    /// Only instructions whose first nibble is set in this mask, bit N is class N (e.g. bit 0xD is DXYN).
+    pub classes: Option<u16>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, address: u16, opcode: u16) -> bool {
+        self.addresses
+            .as_ref()
+            .is_none_or(|range| range.contains(&address))
+            && self
+                .classes
+                .is_none_or(|classes| classes & 1 << (opcode >> 12) != 0)
+    }
+}
+
+/// Where and how to write a trace.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TraceSettings {
+    pub path: PathBuf,
+    pub format: TraceFormat,
+    pub filter: TraceFilter,
+}
+
+/// Writes a line or record for every instruction the emulator executes.
+pub struct Tracer {
+    writer: Box<dyn Write>,
+    format: TraceFormat,
+    filter: TraceFilter,
+    disassembler: Disassembler,
+}
+
+impl Tracer {
+    pub fn new(
+        mut writer: Box<dyn Write>,
+        format: TraceFormat,
+        filter: TraceFilter,
+        config: &Config,
+    ) -> io::Result<Self> {
+        if format == TraceFormat::Binary {
+            writer.write_all(&MAGIC)?;
+            writer.write_all(&VERSION.to_le_bytes())?;
+            writer.write_all(&RECORD_SIZE.to_le_bytes())?;
+        }
+        Ok(Tracer {
+            writer,
+            format,
+            filter,
+            disassembler: Disassembler::with_config(config),
+        })
+    }
+    /// Opens the trace file requested in `config`, if any.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let settings = config.trace.as_ref()?;
+        let tracer = File::create(&settings.path).and_then(|file| {
+            Tracer::new(
+                Box::new(BufWriter::new(file)),
+                settings.format,
+                settings.filter.clone(),
+                config,
+            )
+        });
+        match tracer {
+            Ok(tracer) => Some(tracer),
+            Err(err) => {
+                println!("Could not open trace {}: {}", settings.path.display(), err);
+                None
+            }
+        }
+    }
+    /// Logs the instruction the emulator just fetched, with the state before it executes.
+    pub fn trace(&mut self, emulator: &Emulator) -> io::Result<()> {
+        match self.record(emulator) {
+            Some(record) => self.write(&record),
+            None => Ok(()),
+        }
+    }
+    /// The line or record of the instruction the emulator just fetched, `None` if the filter skips it.
+    pub fn record(&self, emulator: &Emulator) -> Option<Vec<u8>> {
+        let address = emulator.instruction_address as u16;
+        let opcode = emulator.instruction;
+        if !self.filter.matches(address, opcode) {
+            return None;
+        }
+        match self.format {
+            TraceFormat::Text => {
+                // the address of F000 NNNN follows the opcode
+                let byte = |at: usize| emulator.memory.get(at).copied().unwrap_or(0) as u16;
+                let next = byte(address as usize + 2) << 8 | byte(address as usize + 3);
+                let mut line = format!("{:08} {:04X} {:04X} V", emulator.cycles, address, opcode);
+                for register in &emulator.registers {
+                    line.push_str(&format!(" {:02X}", register.v));
+                }
+                line.push_str(&format!(
+                    " I {:04X} SP {:X} DT {:02X} ST {:02X} ; {}\n",
+                    emulator.index,
+                    emulator.stack.len(),
+                    emulator.delay_timer,
+                    emulator.sound_timer,
+                    self.disassembler.disassemble_with_operand(opcode, next)
+                ));
+                Some(line.into_bytes())
+            }
+            TraceFormat::Binary => {
+                let mut record = Vec::with_capacity(RECORD_SIZE as usize);
+                record.extend_from_slice(&emulator.cycles.to_le_bytes());
+                record.extend_from_slice(&address.to_le_bytes());
+                record.extend_from_slice(&opcode.to_le_bytes());
+                record.extend(emulator.registers.iter().map(|r| r.v));
+                record.extend_from_slice(&emulator.index.to_le_bytes());
+                record.push(emulator.stack.len() as u8);
+                record.push(emulator.delay_timer as u8);
+                record.push(emulator.sound_timer as u8);
+                record.push(0);
+                Some(record)
+            }
+        }
+    }
+    /// Writes a line or record made by [`Tracer::record`].
+    pub fn write(&mut self, record: &[u8]) -> io::Result<()> {
+        self.writer.write_all(record)
+    }
+}
+
+/// Parses an inclusive address range like `200-2FF`.
+pub fn parse_range(range: &str) -> Option<RangeInclusive<u16>> {
+    let (start, end) = range.split_once('-')?;
+    let start = u16::from_str_radix(start.trim_start_matches("0x"), 16).ok()?;
+    let end = u16::from_str_radix(end.trim_start_matches("0x"), 16).ok()?;
+    (start <= end).then_some(start..=end)
+}
+
+/// Parses a comma separated list of opcode classes like `0,D,F` into a mask.
+pub fn parse_classes(classes: &str) -> Option<u16> {
+    classes.split(',').try_fold(0, |mask, class| {
+        let class = u8::from_str_radix(class.trim(), 16).ok()?;
+        (class < 16).then_some(mask | 1 << class)
+    })
+}
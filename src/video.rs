@@ -0,0 +1,200 @@
+//! Recording of presented frames as an animated GIF or a sequence of PPM images.
+//!
+//! Identical consecutive frames are merged into one longer frame. The canvas keeps its size when a
+//! SUPER-CHIP program switches resolution, low resolution pixels are drawn twice as large.
+//!
+//! A PPM sequence is written into a directory as `frame00000.ppm`, `frame00001.ppm`, ... together with
+//! `frames.txt`, which lists how long each image is shown in the format of the ffmpeg concat demuxer:
+//!
+//! ```sh
+//! ffmpeg -f concat -i frames.txt clip.mp4
+//! ```
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{config::Config, emulator::Graphics};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VideoFormat {
+    Gif,
+    PpmSequence,
+}
+
+impl VideoFormat {
+    /// `.gif` files become GIFs, anything else a directory of PPM images.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("gif") => VideoFormat::Gif,
+            _ => VideoFormat::PpmSequence,
+        }
+    }
+}
+
+enum Output {
+    Gif(gif::Encoder<BufWriter<File>>),
+    PpmSequence { directory: PathBuf, images: usize },
+}
+
+/// Collects presented frames and writes them out at 60 frames per second.
+pub struct VideoRecorder {
+    pub path: PathBuf,
+    output: Output,
+    palette: [[u8; 3]; 4],
+    /// Size of a high resolution pixel on the canvas.
+    pixel: usize,
+    /// Frame that is still being shown and the number of frames it has been shown for.
+    pending: Option<(Graphics, u32)>,
+    /// Frames written out so far.
+    frames: u32,
+    /// ffconcat playlist of the PPM sequence.
+    playlist: String,
+}
+
+impl VideoRecorder {
+    /// Starts a recording, `scale` is the size of a low resolution pixel.
+    pub fn create(path: &Path, palette: &[[u8; 3]; 4], scale: u32) -> io::Result<Self> {
+        let pixel = (scale as usize / 2).max(1);
+        let width = pixel
+            .checked_mul(128)
+            .and_then(|width| u16::try_from(width).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "video scale too large"))?;
+        let height = width / 2;
+        let output = match VideoFormat::from_path(path) {
+            VideoFormat::Gif => {
+                let writer = BufWriter::new(File::create(path)?);
+                let mut encoder = gif::Encoder::new(writer, width, height, palette.as_flattened())
+                    .map_err(io::Error::other)?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .map_err(io::Error::other)?;
+                Output::Gif(encoder)
+            }
+            VideoFormat::PpmSequence => {
+                fs::create_dir_all(path)?;
+                Output::PpmSequence {
+                    directory: path.to_path_buf(),
+                    images: 0,
+                }
+            }
+        };
+        Ok(VideoRecorder {
+            path: path.to_path_buf(),
+            output,
+            palette: *palette,
+            pixel,
+            pending: None,
+            frames: 0,
+            playlist: String::from("ffconcat version 1.0\n"),
+        })
+    }
+    /// Starts recording to `path` with the palette and video scale of `config`.
+    pub fn from_config(path: &Path, config: &Config) -> Option<Self> {
+        match VideoRecorder::create(path, &config.palette, config.video_scale) {
+            Ok(recorder) => Some(recorder),
+            Err(err) => {
+                println!("Could not record video to {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+    /// Finishes the recording and reports where it went.
+    pub fn stop(self) {
+        let (path, frames) = (self.path.clone(), self.frames());
+        match self.finish() {
+            Ok(()) => println!("Recorded {} frames of video to {}", frames, path.display()),
+            Err(err) => println!("Could not record video to {}: {}", path.display(), err),
+        }
+    }
+    /// Number of frames recorded so far.
+    pub fn frames(&self) -> u32 {
+        self.frames + self.pending.as_ref().map_or(0, |(_, n)| *n)
+    }
+    /// Adds one 60 Hz frame.
+    pub fn push(&mut self, graphics: &Graphics) -> io::Result<()> {
+        if let Some((last, count)) = &mut self.pending {
+            if last.hires == graphics.hires && last.buffer == graphics.buffer {
+                *count += 1;
+                return Ok(());
+            }
+        }
+        self.flush()?;
+        self.pending = Some((graphics.clone(), 1));
+        Ok(())
+    }
+    /// Writes the last frame and closes the output.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()?;
+        match self.output {
+            Output::Gif(encoder) => encoder.into_inner()?.flush(),
+            Output::PpmSequence { directory, .. } => {
+                fs::write(directory.join("frames.txt"), &self.playlist)
+            }
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        let Some((graphics, count)) = self.pending.take() else {
+            return Ok(());
+        };
+        let (start, end) = (self.frames, self.frames + count);
+        self.frames = end;
+        let indices = self.render(&graphics);
+        let (width, height) = (128 * self.pixel, 64 * self.pixel);
+        match &mut self.output {
+            Output::Gif(encoder) => {
+                let mut frame =
+                    gif::Frame::from_indexed_pixels(width as u16, height as u16, indices, None);
+                // GIF delays are in 1/100 s, round the running time so the delays add up to 60 fps
+                frame.delay = (centiseconds(end) - centiseconds(start)) as u16;
+                encoder.write_frame(&frame).map_err(io::Error::other)
+            }
+            Output::PpmSequence { directory, images } => {
+                let name = format!("frame{:05}.ppm", images);
+                *images += 1;
+                let mut writer = BufWriter::new(File::create(directory.join(&name))?);
+                write!(writer, "P6\n{} {}\n255\n", width, height)?;
+                for index in indices {
+                    writer.write_all(&self.palette[index as usize])?;
+                }
+                writer.flush()?;
+                self.playlist.push_str(&format!(
+                    "file '{}'\nduration {:.6}\n",
+                    name,
+                    count as f64 / 60.0
+                ));
+                Ok(())
+            }
+        }
+    }
+    /// Palette indices of the canvas.
+    fn render(&self, graphics: &Graphics) -> Vec<u8> {
+        let (width, height) = (128 * self.pixel, 64 * self.pixel);
+        let size = width / graphics.width();
+        let mut indices = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                indices.push(graphics.color(x / size, y / size) & 0x3);
+            }
+        }
+        indices
+    }
+}
+
+fn centiseconds(frames: u32) -> u32 {
+    (frames * 100 + 30) / 60
+}
+
+/// First unused `<rom>.clip<n>.gif` next to the ROM.
+pub fn next_path(rom: &Path) -> PathBuf {
+    (0..)
+        .map(|n| {
+            let mut path = rom.as_os_str().to_owned();
+            path.push(format!(".clip{}.gif", n));
+            PathBuf::from(path)
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
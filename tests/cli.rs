@@ -0,0 +1,90 @@
+#![cfg(feature = "sdl")]
+
+use std::process::{Command, Output};
+
+use chip8::{config::Config, emulator::Emulator, movie::Movie, platform::Platform, prep_buffer};
+
+fn chip8(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("chip8 should start")
+}
+
+#[test]
+fn help_succeeds() {
+    let output = chip8(&["emu", "--help"]);
+    assert!(output.status.success(), "--help should exit with 0");
+    let help = String::from_utf8_lossy(&output.stdout);
+    assert!(help.contains("--platform"), "Help should list the options");
+}
+
+#[test]
+fn usage_errors_exit_with_2() {
+    for args in [
+        &["emu"][..],
+        &["dis", "roms/1-chip8-logo.ch8", "--platform", "nes"],
+        &["frobnicate"],
+    ] {
+        let output = chip8(args);
+        assert_eq!(
+            output.status.code(),
+            Some(2),
+            "{:?} should be a usage error",
+            args
+        );
+    }
+}
+
+#[test]
+fn runtime_errors_exit_with_1() {
+    let output = chip8(&["dis", "missing.ch8"]);
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "A missing ROM should fail with 1"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("error:") && stderr.contains("missing.ch8"),
+        "The error should name the ROM: {}",
+        stderr
+    );
+    assert!(!stderr.contains("panicked"), "chip8 should not panic");
+}
+
+#[test]
+fn headless_replay_checks_the_final_frame() {
+    let mut buffer = std::fs::read("roms/1-chip8-logo.ch8").unwrap();
+    buffer.resize(4096, 0);
+    prep_buffer(&mut buffer);
+    let emulator = Emulator::with_config(&Config::new(Platform::Chip8));
+    let mut movie = Movie::new(&emulator, &buffer);
+    movie.frames = vec![0; 60];
+    let path = std::env::temp_dir().join(format!("chip8-cli-{}.c8m", std::process::id()));
+    movie.save_file(&path).unwrap();
+    let path = path.to_str().unwrap();
+
+    let replay = |expect: &[&str]| {
+        let mut args = vec!["emu", "roms/1-chip8-logo.ch8", "--play", path, "--headless"];
+        args.extend(expect);
+        chip8(&args)
+    };
+    let output = replay(&[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let digest = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Final frame: "))
+        .expect("the final frame should be printed")
+        .to_string();
+    let matching = replay(&["--expect", &digest]);
+    let different = replay(&["--expect", "0000000000000000000000000000000000000000"]);
+    std::fs::remove_file(path).unwrap();
+    assert!(matching.status.success(), "The expected frame should pass");
+    assert_eq!(
+        different.status.code(),
+        Some(1),
+        "A different final frame should fail"
+    );
+}
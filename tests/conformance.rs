@@ -0,0 +1,13 @@
+use std::path::Path;
+
+use chip8::conformance::{print_results, run_all};
+
+#[test]
+fn bundled_roms_match_golden_frames() {
+    let roms = Path::new(env!("CARGO_MANIFEST_DIR")).join("roms");
+    let results = run_all(&roms);
+    assert!(
+        print_results(&results),
+        "Final frames should match the golden results"
+    );
+}